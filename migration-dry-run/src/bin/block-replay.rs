@@ -0,0 +1,169 @@
+//! Replay a block of a live chain on top of the state of its parent, and compare the outcome with
+//! the chain.
+//!
+//! The keys of child tries are kept in the state of the parent, so that the state root can match
+//! the chain, but the content of child tries is not scraped. Hence, blocks that touch a child trie,
+//! e.g. a crowdloan contribution, cannot be replayed.
+
+use log::*;
+use frame_system::Phase;
+use node_runtime::{Block, Executive, System};
+use remote_externalities::{Builder, CacheMode};
+use sp_core::H256 as Hash;
+use sp_runtime::{generic::SignedBlock, traits::Header as _};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+const LOG_TARGET: &'static str = "block-replay";
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+	name = "block-replay",
+	about = "execute a block of a live chain with `node-runtime` on top of the state of its parent"
+)]
+struct Opt {
+	/// The hash of the block to replay.
+	#[structopt(long)]
+	block: Hash,
+
+	/// The websocket endpoint of the node, used to fetch the block.
+	#[structopt(long, default_value = "ws://localhost:9944")]
+	ws_uri: String,
+
+	/// The http endpoint of the node, used to scrape the state.
+	#[structopt(long, default_value = "http://localhost:9933")]
+	http_uri: String,
+
+	/// The cache mode of the state, one of `none`, `use` or `force`.
+	#[structopt(long, default_value = "use")]
+	cache: CacheMode,
+
+	/// The directory of the cache files.
+	#[structopt(long)]
+	cache_dir: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> () {
+	let _ = env_logger::Builder::from_default_env()
+		.format_module_path(true)
+		.format_level(true)
+		.try_init();
+	let opt = Opt::from_args();
+
+	let client = sub_storage::create_ws_client(&opt.ws_uri).await;
+	let block = sub_storage::get_block::<SignedBlock<Block>>(&client, opt.block)
+		.await
+		.expect("Block not found")
+		.block;
+	let (header, extrinsics) = (block.header, block.extrinsics);
+	let parent = *header.parent_hash();
+	info!(
+		target: LOG_TARGET,
+		"replaying block #{} ({:?}) with {} extrinsics on top of {:?}",
+		header.number(),
+		opt.block,
+		extrinsics.len(),
+		parent,
+	);
+
+	let mut builder =
+		Builder::new().uri(opt.http_uri).at(parent).cache_mode(opt.cache).keep_child_roots(true);
+	if let Some(dir) = opt.cache_dir {
+		builder = builder.cache_dir(dir);
+	}
+	let mut state = builder.build().await;
+
+	let (weights, local_header, events) = state.execute_with(|| {
+		Executive::initialize_block(&header);
+
+		let mut weights = vec![];
+		for (index, extrinsic) in extrinsics.into_iter().enumerate() {
+			let weight_before = System::block_weight().total();
+			match Executive::apply_extrinsic(extrinsic) {
+				Ok(Ok(())) => {}
+				Ok(Err(why)) => {
+					warn!(target: LOG_TARGET, "extrinsic #{} failed: {:?}", index, why)
+				}
+				Err(why) => {
+					error!(target: LOG_TARGET, "extrinsic #{} is invalid: {:?}", index, why)
+				}
+			}
+			weights.push(System::block_weight().total().saturating_sub(weight_before));
+		}
+
+		let local_header = Executive::finalize_block();
+		(weights, local_header, System::events())
+	});
+
+	let phase_events = |phase: Phase| {
+		events.iter().filter(move |e| e.phase == phase).map(|e| &e.event).collect::<Vec<_>>()
+	};
+	println!("initialization: {:?}", phase_events(Phase::Initialization));
+	for (index, weight) in weights.iter().enumerate() {
+		println!(
+			"extrinsic #{}: weight = {}, events = {:?}",
+			index,
+			weight,
+			phase_events(Phase::ApplyExtrinsic(index as u32)),
+		);
+	}
+	println!("finalization: {:?}", phase_events(Phase::Finalization));
+	println!("total weight: {}", weights.iter().sum::<u64>());
+
+	let (local_root, chain_root) = (local_header.state_root(), header.state_root());
+	if local_root == chain_root {
+		info!(target: LOG_TARGET, "state root matches the chain: {:?}", chain_root);
+	} else {
+		error!(
+			target: LOG_TARGET,
+			"state root mismatch: local {:?}, chain {:?}",
+			local_root,
+			chain_root,
+		);
+		std::process::exit(1);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use remote_externalities::{ext_pairs, save_snapshot, snapshot, Compression, SnapshotHeader};
+	use sp_core::storage::{StorageData, StorageKey};
+
+	#[tokio::test]
+	async fn parent_state_of_snapshot_is_exact() {
+		let path = std::env::temp_dir().join("block-replay-parent.bin");
+		let resaved = std::env::temp_dir().join("block-replay-parent-resaved.bin");
+		let code = node_runtime::WASM_BINARY.expect("wasm binary is built").to_vec();
+		let pair = |k: &[u8], v: Vec<u8>| (StorageKey(k.to_vec()), StorageData(v));
+		let pairs = vec![
+			pair(b":child_storage:default:foo", vec![1; 32]),
+			pair(b":code", code),
+			pair(b"foo", b"bar".to_vec()),
+		];
+		let header = SnapshotHeader::new(
+			Default::default(),
+			Default::default(),
+			Default::default(),
+			Default::default(),
+			Default::default(),
+			Compression::None,
+		);
+		snapshot::save(&path, header, &pairs).unwrap();
+
+		// the state holds the real code and the child trie root, and nothing else, e.g. no
+		// `:heappages`.
+		let mut state = Builder::new().from_file(&path).keep_child_roots(true).build().await;
+		assert_eq!(ext_pairs(&mut state), pairs);
+
+		// hence, its root is stable across snapshots.
+		let root = save_snapshot(&mut state, &resaved).unwrap().state_root;
+		let mut rebuilt = Builder::new().from_file(&resaved).keep_child_roots(true).build().await;
+		assert_eq!(ext_pairs(&mut rebuilt), pairs);
+		assert_eq!(save_snapshot(&mut rebuilt, &resaved).unwrap().state_root, root);
+
+		std::fs::remove_file(path).unwrap();
+		std::fs::remove_file(resaved).unwrap();
+	}
+}
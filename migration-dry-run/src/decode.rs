@@ -0,0 +1,136 @@
+//! Check that all of the values in storage can be decoded as the types denoted by the metadata.
+//!
+//! The concrete types of the type names are looked up in a [`TypeRegistry`] (see
+//! [`remote_externalities::decode`]). Values of items with unknown types are skipped, and reported
+//! as such.
+
+use frame_metadata::{DecodeDifferent, RuntimeMetadata, RuntimeMetadataPrefixed, StorageEntryType};
+use remote_externalities::{
+	decode::{Error, TypeRegistry},
+	ext_pairs, HexDisplayExt, TestExternalities,
+};
+use sp_core::hashing::twox_128;
+use std::{
+	collections::BTreeMap,
+	fmt::{Display, Formatter, Result as FmtResult},
+};
+
+/// Maximum number of sample keys that are reported per item.
+const MAX_SAMPLES: usize = 5;
+
+/// The outcome of checking a single storage item.
+#[derive(Clone, Debug, Default)]
+pub struct ItemReport {
+	/// Name of the module.
+	pub module: String,
+	/// Name of the storage item.
+	pub item: String,
+	/// Name of the type of the values.
+	pub value_type: String,
+	/// Number of keys checked.
+	pub checked: usize,
+	/// Number of keys that failed to decode.
+	pub failed: usize,
+	/// Number of keys that were not checked, because their value type is not known.
+	pub skipped: usize,
+	/// Some of the keys that failed to decode, and why.
+	pub samples: Vec<(Vec<u8>, Error)>,
+}
+
+impl Display for ItemReport {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		write!(f, "{}.{} ({}): ", self.module, self.item, self.value_type)?;
+		write!(f, "{} / {} keys failed to decode", self.failed, self.checked)?;
+		if self.skipped > 0 {
+			write!(f, ", {} keys skipped, unknown type", self.skipped)?;
+		}
+		for (key, why) in self.samples.iter() {
+			write!(f, "\n  {:?}: {}", key.hex_display(), why)?;
+		}
+		Ok(())
+	}
+}
+
+/// The string of a metadata field, be it from a native runtime or decoded from a node.
+pub(crate) fn name(input: DecodeDifferent<&'static str, String>) -> String {
+	match input {
+		DecodeDifferent::Encode(name) => name.to_string(),
+		DecodeDifferent::Decoded(name) => name,
+	}
+}
+
+/// The items of a metadata field, be it from a native runtime or decoded from a node.
+pub(crate) fn list<T: Clone>(input: DecodeDifferent<&'static [T], Vec<T>>) -> Vec<T> {
+	match input {
+		DecodeDifferent::Encode(items) => items.to_vec(),
+		DecodeDifferent::Decoded(items) => items,
+	}
+}
+
+/// All of the storage items of `metadata`, keyed by their 32 byte prefix.
+///
+/// Works with both the metadata of a native runtime, and one decoded from a node.
+fn storage_items(metadata: RuntimeMetadataPrefixed) -> BTreeMap<Vec<u8>, ItemReport> {
+	let modules = match metadata.1 {
+		RuntimeMetadata::V12(inner) => list(inner.modules),
+		_ => panic!("Unsupported metadata version. Please make an issue."),
+	};
+
+	let mut items = BTreeMap::new();
+	for module in modules {
+		let storage = match module.storage {
+			Some(DecodeDifferent::Encode(storage)) => (storage.0)(),
+			Some(DecodeDifferent::Decoded(storage)) => storage,
+			None => continue,
+		};
+		let module = name(storage.prefix);
+		for entry in list(storage.entries) {
+			let item = name(entry.name);
+			let value_type = match entry.ty {
+				StorageEntryType::Plain(value) => value,
+				StorageEntryType::Map { value, .. } => value,
+				StorageEntryType::DoubleMap { value, .. } => value,
+			};
+			let mut prefix = twox_128(module.as_bytes()).to_vec();
+			prefix.extend_from_slice(&twox_128(item.as_bytes()));
+			let report = ItemReport {
+				module: module.clone(),
+				item,
+				value_type: name(value_type),
+				..Default::default()
+			};
+			items.insert(prefix, report);
+		}
+	}
+	items
+}
+
+/// Try and decode all of the values of `state` as per `metadata`.
+///
+/// Returns the report of all of the items that have at least one key in `state`. Keys that do not
+/// belong to any storage item of the metadata are ignored.
+pub fn check(
+	state: &mut TestExternalities,
+	metadata: RuntimeMetadataPrefixed,
+	registry: &TypeRegistry,
+) -> Vec<ItemReport> {
+	let mut items = storage_items(metadata);
+	for (key, value) in ext_pairs(state) {
+		let report = match key.0.get(..32).and_then(|prefix| items.get_mut(prefix)) {
+			Some(report) => report,
+			None => continue,
+		};
+		match registry.decode(&report.value_type, &value.0) {
+			Ok(_) => report.checked += 1,
+			Err(Error::UnknownType(_)) => report.skipped += 1,
+			Err(why) => {
+				report.checked += 1;
+				report.failed += 1;
+				if report.samples.len() < MAX_SAMPLES {
+					report.samples.push((key.0, why));
+				}
+			}
+		}
+	}
+	items.into_values().filter(|r| r.checked + r.skipped > 0).collect()
+}
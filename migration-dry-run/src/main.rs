@@ -1,30 +1,232 @@
 use log::*;
-use frame_support::traits::OnRuntimeUpgrade;
-use remote_externalities::TestExternalities;
-use node_runtime::{CustomMigrations, AllModules, System};
+use frame_support::{
+	traits::{Get, OnRuntimeUpgrade, PalletVersion, PALLET_VERSION_STORAGE_KEY_POSTFIX},
+	storage::unhashed,
+	weights::Weight,
+};
+use frame_metadata::RuntimeMetadata;
+use remote_externalities::{Builder, CacheMode, CacheName, TestExternalities};
+use node_runtime::{CustomMigrations, AllModules, Runtime, System};
+use sp_core::{hashing::twox_128, H256 as Hash};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+mod decode;
+use remote_externalities::decode::TypeRegistry;
 
 const LOG_TARGET: &'static str = "migration-dry-run";
 
 /// Note that the order is important here.
 type AllRuntimeMigrations = (System, CustomMigrations, AllModules);
 
+#[derive(Debug, StructOpt)]
+#[structopt(
+	name = "migration-dry-run",
+	about = "run the runtime migrations of `node-runtime` on the state of a live chain"
+)]
+struct Opt {
+	/// The node to connect to.
+	#[structopt(long, default_value = "http://localhost:9933")]
+	uri: String,
+
+	/// The block hash at which the state is scraped. If not provided, the latest finalized head
+	/// is used.
+	#[structopt(long)]
+	at: Option<Hash>,
+
+	/// The cache mode, one of `none`, `use` (use the cache if it exists, else create it) or
+	/// `force` (always create a new cache).
+	#[structopt(long, default_value = "use")]
+	cache: CacheMode,
+
+	/// The name of the cache file. If not provided, it is derived from the chain, block and
+	/// pallets.
+	#[structopt(long)]
+	cache_name: Option<String>,
+
+	/// The directory of the cache files.
+	#[structopt(long)]
+	cache_dir: Option<PathBuf>,
+
+	/// The pallets to scrape, and to report the storage versions of. Can be repeated. If not
+	/// provided, the entire state is scraped and all pallets are reported.
+	///
+	/// Note that the migrations of all pallets are executed regardless.
+	#[structopt(long = "pallet")]
+	pallets: Vec<String>,
+
+	/// Fail if any value could not be checked, because the concrete type of its item is not
+	/// registered.
+	#[structopt(long)]
+	strict: bool,
+}
+
+/// The names of all of the pallets of the runtime.
+fn runtime_pallets() -> Vec<String> {
+	match Runtime::metadata().1 {
+		RuntimeMetadata::V12(inner) => {
+			decode::list(inner.modules).into_iter().map(|m| decode::name(m.name)).collect()
+		}
+		_ => panic!("Unsupported metadata version. Please make an issue."),
+	}
+}
+
+/// Read the storage version of all of the given pallets.
+fn pallet_versions(
+	state: &mut TestExternalities,
+	pallets: &[String],
+) -> Vec<Option<PalletVersion>> {
+	state.execute_with(|| {
+		pallets
+			.iter()
+			.map(|pallet| {
+				let mut key = twox_128(pallet.as_bytes()).to_vec();
+				key.extend_from_slice(&twox_128(PALLET_VERSION_STORAGE_KEY_POSTFIX));
+				unhashed::get::<PalletVersion>(&key)
+			})
+			.collect()
+	})
+}
+
+fn display_version(version: Option<PalletVersion>) -> String {
+	version.map_or_else(|| "-".to_string(), |v| format!("{}.{}.{}", v.major, v.minor, v.patch))
+}
+
+type AccountId = <Runtime as frame_system::Config>::AccountId;
+type Index = <Runtime as frame_system::Config>::Index;
+type AccountData = <Runtime as frame_system::Config>::AccountData;
+type Balance = <Runtime as pallet_balances::Config>::Balance;
+
+/// The concrete types of the runtime that are commonly used in storage, and the structs of the
+/// storage items that migrations most often touch.
+fn runtime_types() -> TypeRegistry {
+	use pallet_staking::{slashing, *};
+
+	let mut registry = TypeRegistry::new();
+	registry
+		.register::<AccountId>(&["T::AccountId"])
+		.register::<<Runtime as frame_system::Config>::BlockNumber>(&["T::BlockNumber"])
+		.register::<<Runtime as frame_system::Config>::Hash>(&["T::Hash"])
+		.register::<Index>(&["T::Index"])
+		.register::<Balance>(&["T::Balance", "BalanceOf<T>", "BalanceOf<T, I>"]);
+
+	// system and balances.
+	registry
+		.register::<AccountData>(&["T::AccountData", "AccountData<T::Balance>"])
+		.register::<frame_system::AccountInfo<Index, AccountData>>(&[
+			"AccountInfo<T::Index, T::AccountData>"
+		])
+		.register::<pallet_balances::BalanceLock<Balance>>(&["BalanceLock<T::Balance>"]);
+
+	// staking.
+	registry
+		.register::<StakingLedger<AccountId, Balance>>(&[
+			"StakingLedger<T::AccountId, BalanceOf<T>>"
+		])
+		.register::<Exposure<AccountId, Balance>>(&["Exposure<T::AccountId, BalanceOf<T>>"])
+		.register::<Nominations<AccountId>>(&["Nominations<T::AccountId>"])
+		.register::<ValidatorPrefs>(&["ValidatorPrefs"])
+		.register::<RewardDestination<AccountId>>(&["RewardDestination<T::AccountId>"])
+		.register::<ActiveEraInfo>(&["ActiveEraInfo"])
+		.register::<EraRewardPoints<AccountId>>(&["EraRewardPoints<T::AccountId>"])
+		.register::<Forcing>(&["Forcing"])
+		.register::<UnappliedSlash<AccountId, Balance>>(&[
+			"UnappliedSlash<T::AccountId, BalanceOf<T>>"
+		])
+		.register_opaque::<slashing::SlashingSpans>(&["slashing::SlashingSpans"])
+		.register_opaque::<slashing::SpanRecord<Balance>>(&["slashing::SpanRecord<BalanceOf<T>>"]);
+	registry
+}
+
 struct Executive;
 
 impl Executive {
-	fn migrate(mut state: TestExternalities, tests: Box<dyn Fn() -> ()>) {
+	/// Run all of the migrations on `state`, and return the list of failed checks.
+	///
+	/// If `strict`, values of unknown types are also considered a failure.
+	fn migrate(mut state: TestExternalities, pallets: &[String], strict: bool) -> Vec<String> {
+		let mut failures = vec![];
+		let versions_before = pallet_versions(&mut state, pallets);
+
 		info!(target: LOG_TARGET, "executing pre_migration");
-		state.execute_with(<AllRuntimeMigrations as OnRuntimeUpgrade>::pre_migration).unwrap();
+		if let Err(why) =
+			state.execute_with(<AllRuntimeMigrations as OnRuntimeUpgrade>::pre_migration)
+		{
+			error!(target: LOG_TARGET, "pre_migration failed: {}", why);
+			failures.push(format!("pre_migration: {}", why));
+		}
 
 		info!(target: LOG_TARGET, "executing on_runtime_upgrade");
-		let weight =
+		let weight: Weight =
 			state.execute_with(<AllRuntimeMigrations as OnRuntimeUpgrade>::on_runtime_upgrade);
-		info!(target: LOG_TARGET, "migration weight = {}", weight);
+		let max_weight: Weight =
+			<<Runtime as frame_system::Config>::BlockWeights as Get<_>>::get().max_block;
+		info!(
+			target: LOG_TARGET,
+			"migration weight = {} ({:.2}% of the block weight limit {})",
+			weight,
+			weight as f64 * 100.0 / max_weight as f64,
+			max_weight,
+		);
+		if weight > max_weight {
+			error!(target: LOG_TARGET, "migration weight exceeds the block weight limit");
+			failures.push(format!("weight: {} > {}", weight, max_weight));
+		}
 
 		info!(target: LOG_TARGET, "executing post_migration");
-		state.execute_with(<AllRuntimeMigrations as OnRuntimeUpgrade>::post_migration).unwrap();
+		if let Err(why) =
+			state.execute_with(<AllRuntimeMigrations as OnRuntimeUpgrade>::post_migration)
+		{
+			error!(target: LOG_TARGET, "post_migration failed: {}", why);
+			failures.push(format!("post_migration: {}", why));
+		}
+
+		info!(target: LOG_TARGET, "checking the decodability of all storage values");
+		let reports = decode::check(&mut state, Runtime::metadata(), &runtime_types());
+		let (mut checked, mut failed, mut skipped, mut skipped_items) = (0, 0, 0, 0);
+		for report in reports.iter() {
+			checked += report.checked;
+			failed += report.failed;
+			skipped += report.skipped;
+			if report.failed > 0 {
+				println!("{}", report);
+			} else if report.skipped > 0 {
+				info!(target: LOG_TARGET, "{}", report);
+			}
+			if report.skipped > 0 {
+				skipped_items += 1;
+			}
+		}
+		info!(target: LOG_TARGET, "{} / {} values failed to decode", failed, checked);
+		if skipped > 0 {
+			warn!(
+				target: LOG_TARGET,
+				"{} values of {} items were not checked, because their types are unknown",
+				skipped,
+				skipped_items,
+			);
+		}
+		if failed > 0 {
+			failures.push(format!("decode: {} values failed to decode", failed));
+		}
+		if strict && skipped > 0 {
+			failures.push(format!("decode: {} values of unknown types", skipped));
+		}
 
-		info!(target: LOG_TARGET, "running custom assertions");
-		state.execute_with(tests);
+		let versions_after = pallet_versions(&mut state, pallets);
+		println!("{: <24} {: >10} {: >10}", "pallet", "before", "after");
+		for ((pallet, before), after) in pallets.iter().zip(versions_before).zip(versions_after) {
+			let changed = if before != after { "*" } else { "" };
+			println!(
+				"{: <24} {: >10} {: >10} {}",
+				pallet,
+				display_version(before),
+				display_version(after),
+				changed,
+			);
+		}
+
+		failures
 	}
 }
 
@@ -34,23 +236,32 @@ async fn main() -> () {
 		.format_module_path(true)
 		.format_level(true)
 		.try_init();
+	let opt = Opt::from_args();
+
+	let mut builder = Builder::new().uri(opt.uri).cache_mode(opt.cache);
+	if let Some(at) = opt.at {
+		builder = builder.at(at);
+	}
+	if let Some(name) = opt.cache_name {
+		builder = builder.cache_name(CacheName::Forced(name));
+	}
+	if let Some(dir) = opt.cache_dir {
+		builder = builder.cache_dir(dir);
+	}
+	for pallet in opt.pallets.iter() {
+		builder = builder.module(pallet);
+	}
+	let state = builder.build().await;
 
-	// TODO: nice to have: print all of the pallet versions in storage before and after the
-	// migration.
-
-	let state = remote_externalities::Builder::new()
-		.cache_mode(remote_externalities::CacheMode::UseElseCreate)
-		.cache_name(remote_externalities::CacheName::Forced(
-			"Kusama,0x7f13b9c87b6ba0845ea69a4cde233f2e8979666e86fe62eeba4982da8133023c,.bin".into(),
-		))
-		.build()
-		.await;
-
-	Executive::migrate(
-		state,
-		Box::new(|| {
-			// probably verifications need to come from the runtime as well. This can be a
-			// placeholder for any additional checks.
-		}),
-	)
+	let pallets = if opt.pallets.is_empty() { runtime_pallets() } else { opt.pallets };
+	let failures = Executive::migrate(state, &pallets, opt.strict);
+
+	if failures.is_empty() {
+		info!(target: LOG_TARGET, "all checks passed.");
+	} else {
+		for failure in failures.iter() {
+			error!(target: LOG_TARGET, "failed check: {}", failure);
+		}
+		std::process::exit(1);
+	}
 }
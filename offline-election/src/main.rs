@@ -115,6 +115,12 @@
 //!
 //! Find an example [here](./override_example.json).
 //!
+//! ### Output
+//!
+//! The staking election writes its result as json to the optional `<output>` file. `winners` holds
+//! the elected stashes, and `supports` the support of each of them: `total` and `voters` are the
+//! raw vote weights, and `total_stake` is the total backing in tokens, as a string of base units.
+//!
 //! ## Example usage
 //!
 //! - Run the council election with 25 members.
@@ -176,9 +182,10 @@
 pub use primitives::{AccountId, Balance, BlockNumber, Hash};
 
 use sp_core::crypto::{set_default_ss58_version, Ss58AddressFormat};
-use std::path::PathBuf;
+use std::{convert::TryFrom, path::PathBuf};
 use structopt::StructOpt;
 use sub_storage as storage;
+use sub_tokens::registry::{Registry, SystemProperties};
 
 mod network;
 mod primitives;
@@ -212,12 +219,12 @@ pub struct Opt {
 	#[structopt(long, default_value = "ws://localhost:9944")]
 	uri: String,
 
-	/// Network address format. Can be kusama|polkadot|substrate.
+	/// The name of a known network, e.g. polkadot|kusama|westend|substrate.
 	///
-	/// This will also change the token display name.
+	/// This sets the address format and the token.
 	///
-	/// If not provided, then the spec name of the runtime version at given at will be compared to
-	/// be `polkadot`, `kusama` or `substrate`.
+	/// If not provided, the token and address format are detected from the `system_properties` of
+	/// the node, or else from the spec name of the runtime version at `at`.
 	#[structopt(short, long)]
 	network: Option<String>,
 
@@ -275,6 +282,8 @@ pub struct StakingConfig {
 	count: Option<usize>,
 
 	/// Json output file name. dumps the results into if given.
+	///
+	/// The supports hold the raw vote weights, and the total stake in tokens as `total_stake`.
 	#[structopt(parse(from_os_str))]
 	output: Option<PathBuf>,
 
@@ -292,6 +301,10 @@ pub struct StakingConfig {
 	/// The override file to interpret
 	#[structopt(short, long, parse(from_os_str))]
 	manual_override: Option<PathBuf>,
+
+	/// Ignore the voters with less stake than this amount, e.g. `100 DOT` or `1.5k`.
+	#[structopt(long)]
+	min_voter_stake: Option<sub_tokens::Amount>,
 }
 
 /// Arguments that can be passed to the council sub-command.
@@ -337,24 +350,30 @@ async fn main() -> () {
 	opt.at = Some(at);
 
 	let runtime_version = sub_storage::get_runtime_version(&client, at).await;
-	let spec_name = runtime_version.spec_name;
-	let network_address = opt.clone().network.unwrap_or_else(|| spec_name.into());
-	let address_format = match &network_address[..] {
-		"polkadot" => Ss58AddressFormat::PolkadotAccount,
-		"kusama" => Ss58AddressFormat::KusamaAccount,
-		"substrate" => Ss58AddressFormat::SubstrateAccount,
-		_ => Ss58AddressFormat::SubstrateAccount,
+	let spec_name: String = runtime_version.spec_name.into();
+	let properties = sub_storage::get_system_properties::<SystemProperties>(&client).await;
+
+	// setup address format and currency based on the chain, or the given network.
+	let registry = Registry::default();
+	let token = match &opt.network {
+		Some(network) => {
+			registry.get(network).cloned().unwrap_or_else(|| registry.detect(network, &properties))
+		}
+		None => registry.detect(&spec_name, &properties),
 	};
-
-	// setup address format and currency based on address format.
+	log::info!(target: LOG_TARGET, "detected token {:?} of chain {}", token, spec_name);
+
+	let address_format = token
+		.ss58_format
+		.and_then(|format| u8::try_from(format).ok())
+		.map(|format| {
+			Ss58AddressFormat::try_from(format).unwrap_or(Ss58AddressFormat::Custom(format))
+		})
+		.unwrap_or(Ss58AddressFormat::SubstrateAccount);
 	set_default_ss58_version(address_format);
-	if address_format.eq(&Ss58AddressFormat::PolkadotAccount) {
-		sub_tokens::dynamic::set_name("DOT");
-		sub_tokens::dynamic::set_decimal_points(10_000_000_000);
-	} else if address_format.eq(&Ss58AddressFormat::KusamaAccount) {
-		sub_tokens::dynamic::set_name("KSM");
-		sub_tokens::dynamic::set_decimal_points(1_000_000_000_000);
-	}
+	token
+		.set_dynamic()
+		.unwrap_or_else(|why| panic!("Token {:?} is not supported: {}", token, why));
 
 	// set total issuance
 	network::issuance::set(&client, at).await;
@@ -1,7 +1,9 @@
 use crate::{
 	network,
 	primitives::{AccountId, Balance, Hash},
-	storage, Client, CouncilConfig, Currency, Opt, LOG_TARGET,
+	storage,
+	subcommands::OverrideStake,
+	Client, CouncilConfig, Currency, Opt, LOG_TARGET,
 };
 use sp_npos_elections::*;
 use sp_runtime::traits::{Convert, Zero};
@@ -109,7 +111,7 @@ pub async fn run(client: &Client, opt: Opt, conf: CouncilConfig) {
 
 		#[derive(serde::Serialize, serde::Deserialize)]
 		struct Override {
-			pub voters: Vec<(AccountId, u64, Vec<AccountId>)>,
+			pub voters: Vec<(AccountId, OverrideStake, Vec<AccountId>)>,
 			pub voters_remove: Vec<AccountId>,
 			pub voters_mutate: Vec<VotersMutate>,
 			pub candidates: Vec<AccountId>,
@@ -133,18 +135,20 @@ pub async fn run(client: &Client, opt: Opt, conf: CouncilConfig) {
 		candidates.retain(|c| !manual.candidates_remove.contains(c));
 
 		// add any new votes
-		manual.voters.iter().for_each(|v| {
-			if let Some(mut already_existing_voter) = all_voters.iter_mut().find(|vv| vv.0 == v.0) {
+		manual.voters.iter().for_each(|(who, stake, votes)| {
+			let stake = stake.to_votes(to_votes);
+			if let Some(mut already_existing_voter) = all_voters.iter_mut().find(|vv| &vv.0 == who)
+			{
 				log::warn!(
 					target: LOG_TARGET,
 					"manual override: {:?} is already a voter. Overriding votes and stake.",
-					v.0,
+					who,
 				);
-				already_existing_voter.1 = v.1;
-				already_existing_voter.2 = v.2.clone();
+				already_existing_voter.1 = stake;
+				already_existing_voter.2 = votes.clone();
 			} else {
-				log::warn!(target: LOG_TARGET, "manual override: {:?} is added as voters.", v.0);
-				all_voters.push(v.clone())
+				log::warn!(target: LOG_TARGET, "manual override: {:?} is added as voters.", who);
+				all_voters.push((who.clone(), stake, votes.clone()))
 			}
 		});
 
@@ -10,3 +10,33 @@ pub mod nominator_check;
 pub mod staking;
 /// Validator-check sub-command.
 pub mod validator_check;
+
+use crate::{primitives::Balance, Currency};
+use sp_npos_elections::VoteWeight;
+
+/// The stake of a voter in a manual override file.
+///
+/// Either a raw vote weight, or a token amount such as `"1,000 DOT"` or `"1.5k"`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum OverrideStake {
+	/// A raw vote weight.
+	Votes(VoteWeight),
+	/// A human-readable amount of the chain's token.
+	Amount(String),
+}
+
+impl OverrideStake {
+	/// The vote weight of this stake, converting amounts with `to_votes`.
+	pub fn to_votes(&self, to_votes: impl Fn(Balance) -> VoteWeight) -> VoteWeight {
+		match self {
+			Self::Votes(votes) => *votes,
+			Self::Amount(amount) => to_votes(
+				amount
+					.parse::<Currency>()
+					.unwrap_or_else(|e| panic!("invalid amount `{}` in override: {}", amount, e))
+					.value(),
+			),
+		}
+	}
+}
@@ -3,7 +3,9 @@
 use crate::{
 	network,
 	primitives::{AccountId, Balance, Hash},
-	storage, Client, Currency, Opt, StakingConfig, LOG_TARGET,
+	storage,
+	subcommands::OverrideStake,
+	Client, Currency, Opt, StakingConfig, LOG_TARGET,
 };
 use codec::Encode;
 use pallet_staking::{slashing::SlashingSpans, EraIndex, Exposure, Nominations, StakingLedger};
@@ -175,7 +177,7 @@ pub async fn run(client: &Client, opt: Opt, conf: StakingConfig) {
 	if let Some(path) = conf.manual_override {
 		#[derive(serde::Serialize, serde::Deserialize)]
 		struct Override {
-			voters: Vec<(AccountId, u64, Vec<AccountId>)>,
+			voters: Vec<(AccountId, OverrideStake, Vec<AccountId>)>,
 			voters_remove: Vec<AccountId>,
 			candidates: Vec<AccountId>,
 			candidates_remove: Vec<AccountId>,
@@ -198,16 +200,17 @@ pub async fn run(client: &Client, opt: Opt, conf: StakingConfig) {
 		candidates.retain(|c| !manual.candidates_remove.contains(c));
 
 		// add any new votes
-		manual.voters.iter().for_each(|v| {
+		manual.voters.iter().for_each(|(who, stake, votes)| {
+			let stake = stake.to_votes(to_vote_weight);
 			if let Some(mut already_existing_voter) =
-				all_voters_and_stake.iter_mut().find(|vv| vv.0 == v.0)
+				all_voters_and_stake.iter_mut().find(|vv| &vv.0 == who)
 			{
-				println!("manual override: {:?} is already a voter. Overriding votes.", v.0);
-				already_existing_voter.1 = v.1;
-				already_existing_voter.2 = v.2.clone();
+				println!("manual override: {:?} is already a voter. Overriding votes.", who);
+				already_existing_voter.1 = stake;
+				already_existing_voter.2 = votes.clone();
 			} else {
-				println!("manual override: {:?} is added as voters.", v.0);
-				all_voters_and_stake.push(v.clone())
+				println!("manual override: {:?} is added as voters.", who);
+				all_voters_and_stake.push((who.clone(), stake, votes.clone()))
 			}
 		});
 
@@ -215,6 +218,19 @@ pub async fn run(client: &Client, opt: Opt, conf: StakingConfig) {
 		all_voters_and_stake.retain(|v| !manual.voters_remove.contains(&v.0));
 	}
 
+	if let Some(amount) = conf.min_voter_stake {
+		let min_stake = Currency::from_amount(&amount).expect("Invalid minimum voter stake");
+		let min_votes = to_vote_weight(min_stake.value());
+		let before = all_voters_and_stake.len();
+		all_voters_and_stake.retain(|v| v.1 >= min_votes);
+		log::info!(
+			target: LOG_TARGET,
+			"ignored {} voters with less stake than {}",
+			before - all_voters_and_stake.len(),
+			min_stake,
+		);
+	}
+
 	// add self-vote
 	for c in candidates.iter() {
 		let self_vote = (c.clone(), to_vote_weight(stake_of(c, client, at).await), vec![c.clone()]);
@@ -359,36 +375,33 @@ pub async fn run(client: &Client, opt: Opt, conf: StakingConfig) {
 	if let Some(output_file) = conf.output {
 		use std::fs::File;
 
-		// We can't really use u128 or arbitrary_precision of serde for now, so sadly all I can do
-		// is duplicate the types with u64. Not cool but okay for now.
-		#[derive(serde::Serialize, serde::Deserialize)]
-		struct Support64 {
+		// `total` and `voters` are the raw vote weights, which fit in the u64 of json numbers.
+		// `total_stake` is the total in tokens, serialized as a string so that nothing is lost.
+		#[derive(serde::Serialize)]
+		struct SupportOutput {
 			total: u64,
+			total_stake: Currency,
 			voters: Vec<(AccountId, u64)>,
 		}
 
-		type SupportMap64 = std::collections::BTreeMap<AccountId, Support64>;
-
-		impl From<sp_npos_elections::Support<AccountId>> for Support64 {
-			fn from(t: sp_npos_elections::Support<AccountId>) -> Self {
-				Self {
-					total: t.total.try_into().unwrap(),
-					voters: t
+		let supports_output = supports
+			.into_iter()
+			.map(|(k, v)| {
+				let output = SupportOutput {
+					total: v.total.try_into().unwrap(),
+					total_stake: Currency::from(to_currency(v.total)),
+					voters: v
 						.voters
 						.into_iter()
-						.map(|(w, v)| (w, (v).try_into().unwrap()))
+						.map(|(w, v)| (w, v.try_into().unwrap()))
 						.collect::<Vec<_>>(),
-				}
-			}
-		}
-
-		let mut supports_64 = SupportMap64::new();
-		for (k, v) in supports.into_iter() {
-			supports_64.insert(k, v.into());
-		}
+				};
+				(k, output)
+			})
+			.collect::<BTreeMap<_, _>>();
 
 		let output = serde_json::json!({
-			"supports": supports_64,
+			"supports": supports_output,
 			"winners": elected_stashes,
 		});
 
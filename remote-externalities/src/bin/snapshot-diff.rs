@@ -0,0 +1,74 @@
+//! Print the difference between two remote-externalities snapshots.
+
+use remote_externalities::{
+	diff::{diff, item_names_from_remote, polkadot_types, ItemNames},
+	snapshot,
+};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+	name = "snapshot-diff",
+	about = "print the added, removed and modified keys between two remote-externalities snapshots"
+)]
+struct Opt {
+	/// The snapshot of the old state.
+	before: PathBuf,
+
+	/// The snapshot of the new state.
+	after: PathBuf,
+
+	/// The node to fetch the metadata from, used to name the storage items.
+	///
+	/// The metadata is fetched at the block of the old snapshot. If not provided, the items are
+	/// named by their hex prefix.
+	#[structopt(long)]
+	uri: Option<String>,
+
+	/// If true, all of the changed keys and their values are printed as well.
+	///
+	/// The values are decoded if their type is primitive, or a generic type of polkadot and
+	/// kusama, e.g. `T::AccountId`, or a container of them. Otherwise, they are printed in hex.
+	/// The types are read from the metadata, hence values are only decoded if `--uri` is given.
+	#[structopt(long, short)]
+	values: bool,
+}
+
+#[async_std::main]
+async fn main() -> () {
+	env_logger::Builder::from_default_env().format_module_path(false).format_level(true).init();
+	let opt = Opt::from_args();
+
+	let load = |path: &PathBuf| {
+		snapshot::load(path).unwrap_or_else(|e| panic!("failed to load {:?}: {}", path, e))
+	};
+	let (before_header, before) = load(&opt.before);
+	let (after_header, after) = load(&opt.after);
+
+	println!(
+		"{} @ {:?} (spec {}) -> {} @ {:?} (spec {})",
+		before_header.chain,
+		before_header.at,
+		before_header.spec_version,
+		after_header.chain,
+		after_header.at,
+		after_header.spec_version,
+	);
+
+	let names = match opt.uri {
+		Some(uri) => item_names_from_remote(&uri, before_header.at).await,
+		None => ItemNames::new(),
+	};
+
+	let diff = diff(&before, &after, &names);
+	if diff.is_empty() {
+		println!("no changes.");
+	} else if opt.values {
+		let mut output = String::new();
+		diff.display_values(&mut output, &polkadot_types()).unwrap();
+		print!("{}", output);
+	} else {
+		print!("{}", diff);
+	}
+}
@@ -0,0 +1,276 @@
+//! Inspection and management of the cache files created by [`crate::Builder`].
+//!
+//! All functions in this module only look at files with a `.bin` extension in the given directory.
+//! The information of each file is read from its [`crate::snapshot::SnapshotHeader`]. For files
+//! that are not valid snapshots (e.g. legacy cache files), the chain, block hash and key filters
+//! are parsed from the [`crate::CacheName::Auto`] naming scheme, if possible, and the creation
+//! time is the last modification time of the file.
+
+use crate::{snapshot, Hash};
+use std::{
+	fmt::{Display, Formatter, Result as FmtResult},
+	fs, io,
+	path::{Path, PathBuf},
+	str::FromStr,
+	time::{Duration, SystemTime},
+};
+
+/// Information about a single cache file.
+#[derive(Clone, Debug)]
+pub struct CacheInfo {
+	/// Path to the file.
+	pub path: PathBuf,
+	/// The chain name, if known.
+	pub chain: Option<String>,
+	/// The block hash, if known.
+	pub at: Option<Hash>,
+	/// The key filters used to create this cache. Empty means the entire chain.
+	pub filters: Vec<String>,
+	/// Number of keys in the cache, if it could be read.
+	pub key_count: Option<u64>,
+	/// The runtime spec version at the block of the cache, if it could be read.
+	pub spec_version: Option<u32>,
+	/// Size of the file in bytes.
+	pub size: u64,
+	/// Last modification time of the file.
+	pub modified: SystemTime,
+	/// Creation time of the snapshot, as per its header, else the last modification time of the
+	/// file. Unlike the latter, this does not change when the file is copied or touched.
+	pub created: SystemTime,
+}
+
+impl CacheInfo {
+	/// Read the information of the cache file at `path`.
+	pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		let path = path.as_ref().to_path_buf();
+		let metadata = fs::metadata(&path)?;
+		let modified = metadata.modified()?;
+		let header = snapshot::read_header(&path);
+		let created = match &header {
+			Ok(header) => SystemTime::UNIX_EPOCH + Duration::from_secs(header.created),
+			Err(_) => modified,
+		};
+		let (chain, at, filters, key_count, spec_version) = match header {
+			Ok(header) => (
+				Some(header.chain),
+				Some(header.at),
+				header.filters,
+				Some(header.key_count),
+				Some(header.spec_version),
+			),
+			Err(why) => {
+				log::debug!(target: crate::LOG_TARGET, "no header in {:?} due to: {}", path, why);
+				let (chain, at, filters) = path
+					.file_stem()
+					.and_then(|s| s.to_str())
+					.and_then(parse_auto_name)
+					.map(|(c, a, m)| (Some(c), Some(a), m))
+					.unwrap_or((None, None, vec![]));
+				(chain, at, filters, None, None)
+			}
+		};
+
+		Ok(Self {
+			size: metadata.len(),
+			modified,
+			created,
+			path,
+			chain,
+			at,
+			filters,
+			key_count,
+			spec_version,
+		})
+	}
+
+	/// The age of the snapshot, based on its creation time.
+	pub fn age(&self) -> Duration {
+		SystemTime::now().duration_since(self.created).unwrap_or_default()
+	}
+}
+
+impl Display for CacheInfo {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		write!(
+			f,
+			"{}({}) @ {} [{}] => {} keys, {} bytes, {}s old ({})",
+			self.chain.as_deref().unwrap_or("?"),
+			self.spec_version.map_or_else(|| "?".to_string(), |v| v.to_string()),
+			self.at.map_or_else(|| "?".to_string(), |h| format!("{:?}", h)),
+			if self.filters.is_empty() { "all".to_string() } else { self.filters.join(",") },
+			self.key_count.map_or_else(|| "?".to_string(), |c| c.to_string()),
+			self.size,
+			self.age().as_secs(),
+			self.path.display(),
+		)
+	}
+}
+
+/// Criteria for removing cache files.
+#[derive(Copy, Clone, Debug)]
+pub enum PruneMode {
+	/// Remove all files that were created longer than the given duration ago.
+	OlderThan(Duration),
+	/// Keep only the given number of most recently created files, and remove the rest.
+	KeepLatest(usize),
+}
+
+/// Parse a file stem that follows the `{chain},{hash},{filters?}` naming scheme.
+fn parse_auto_name(stem: &str) -> Option<(String, Hash, Vec<String>)> {
+	let mut parts = stem.split(',');
+	let chain = parts.next()?.to_string();
+	let hash = parts.next()?;
+	let at = Hash::from_str(hash.trim_start_matches("0x")).ok()?;
+	let filters = parts.filter(|f| !f.is_empty()).map(|f| f.to_string()).collect::<Vec<_>>();
+	Some((chain, at, filters))
+}
+
+/// List all of the cache files in `dir`, most recently created first.
+pub fn list<P: AsRef<Path>>(dir: P) -> io::Result<Vec<CacheInfo>> {
+	let mut infos = vec![];
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+		if path.is_file() && path.extension().map_or(false, |e| e == "bin") {
+			infos.push(CacheInfo::from_path(path)?);
+		}
+	}
+	infos.sort_by_key(|i| std::cmp::Reverse(i.created));
+	Ok(infos)
+}
+
+/// Remove the cache files in `dir` that match `mode`.
+///
+/// Returns the information of the removed files.
+pub fn prune<P: AsRef<Path>>(dir: P, mode: PruneMode) -> io::Result<Vec<CacheInfo>> {
+	let infos = list(dir)?;
+	let to_remove = match mode {
+		PruneMode::OlderThan(max_age) => {
+			infos.into_iter().filter(|i| i.age() > max_age).collect::<Vec<_>>()
+		}
+		PruneMode::KeepLatest(count) => infos.into_iter().skip(count).collect::<Vec<_>>(),
+	};
+
+	for info in to_remove.iter() {
+		log::info!(target: crate::LOG_TARGET, "removing cache file {:?}", info.path);
+		fs::remove_file(&info.path)?;
+	}
+	Ok(to_remove)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		snapshot::{Compression, SnapshotHeader},
+		KeyPair,
+	};
+	use sp_core::storage::{StorageData, StorageKey};
+
+	fn test_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("remote-ext-{}", name));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	fn test_data(count: usize) -> Vec<KeyPair> {
+		(0..count).map(|i| (StorageKey(vec![i as u8]), StorageData(vec![i as u8; 4]))).collect()
+	}
+
+	fn write_cache(dir: &Path, name: &str, chain: &str, at: Hash, count: usize) {
+		write_cache_created(dir, name, chain, at, count, None);
+	}
+
+	fn write_cache_created(
+		dir: &Path,
+		name: &str,
+		chain: &str,
+		at: Hash,
+		count: usize,
+		created: Option<u64>,
+	) {
+		let mut header = SnapshotHeader::new(
+			chain.into(),
+			at,
+			Default::default(),
+			42,
+			vec!["Staking".into()],
+			Compression::None,
+		);
+		header.created = created.unwrap_or(header.created);
+		snapshot::save(dir.join(name), header, &test_data(count)).unwrap();
+	}
+
+	#[test]
+	fn can_list_cache() {
+		let dir = test_dir("list");
+		let hash = Hash::repeat_byte(1);
+		write_cache(&dir, "forced.bin", "Polkadot", hash, 2);
+		fs::write(
+			dir.join(format!("Kusama,{:?},Staking,System.bin", hash)),
+			bincode::serialize(&test_data(3)).unwrap(),
+		)
+		.unwrap();
+		fs::write(dir.join("not-a-cache.txt"), b"foo").unwrap();
+
+		let mut infos = list(&dir).unwrap();
+		infos.sort_by_key(|i| i.path.clone());
+		assert_eq!(infos.len(), 2);
+
+		// legacy file, parsed from name.
+		assert_eq!(infos[0].chain, Some("Kusama".to_string()));
+		assert_eq!(infos[0].at, Some(hash));
+		assert_eq!(infos[0].filters, vec!["Staking".to_string(), "System".to_string()]);
+		assert_eq!(infos[0].key_count, None);
+		assert_eq!(infos[0].size, fs::metadata(&infos[0].path).unwrap().len());
+
+		// snapshot, parsed from header.
+		assert_eq!(infos[1].chain, Some("Polkadot".to_string()));
+		assert_eq!(infos[1].at, Some(hash));
+		assert_eq!(infos[1].filters, vec!["Staking".to_string()]);
+		assert_eq!(infos[1].key_count, Some(2));
+		assert_eq!(infos[1].spec_version, Some(42));
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn can_prune_cache() {
+		let dir = test_dir("prune");
+		for i in 0..3 {
+			let at = Hash::repeat_byte(i);
+			write_cache(&dir, &format!("Kusama,{:?},Staking.bin", at), "Kusama", at, 1);
+		}
+
+		assert!(prune(&dir, PruneMode::OlderThan(Duration::from_secs(3600))).unwrap().is_empty());
+		assert_eq!(list(&dir).unwrap().len(), 3);
+
+		assert_eq!(prune(&dir, PruneMode::KeepLatest(1)).unwrap().len(), 2);
+		assert_eq!(list(&dir).unwrap().len(), 1);
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn age_is_read_from_the_header() {
+		let dir = test_dir("age");
+		let day = 24 * 3600;
+		let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+		// just written, hence recently modified, but created two days ago.
+		let at = Hash::repeat_byte(1);
+		write_cache_created(&dir, "old.bin", "Kusama", at, 1, Some(now - 2 * day));
+		write_cache(&dir, "new.bin", "Kusama", at, 1);
+		fs::write(dir.join("legacy.bin"), bincode::serialize(&test_data(1)).unwrap()).unwrap();
+
+		let infos = list(&dir).unwrap();
+		assert_eq!(infos.last().unwrap().path, dir.join("old.bin"));
+		assert!(infos.last().unwrap().age() >= Duration::from_secs(2 * day));
+
+		let removed = prune(&dir, PruneMode::OlderThan(Duration::from_secs(day))).unwrap();
+		assert_eq!(removed.len(), 1);
+		assert_eq!(removed[0].path, dir.join("old.bin"));
+		assert_eq!(list(&dir).unwrap().len(), 2);
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
@@ -0,0 +1,273 @@
+//! Decoding storage values by the names of their types, as found in the metadata.
+//!
+//! The metadata only contains the names of the types, e.g. `Vec<(T::AccountId, BalanceOf<T>)>`.
+//! Hence, the concrete types of all names that are not primitives or one of the well-known generic
+//! containers (`Vec`, `Option`, `BTreeMap`, tuples, arrays, ...) must be registered in a
+//! [`TypeRegistry`].
+
+use crate::HexDisplayExt;
+use codec::{Compact, Decode, Input};
+use std::{
+	collections::BTreeMap,
+	fmt::{Debug, Display, Formatter, Result as FmtResult},
+};
+
+/// A function that decodes a single value of some type from the input, and displays it.
+type Decoder = fn(&mut &[u8]) -> Result<String, codec::Error>;
+
+fn decoder<T: Decode + Debug>(input: &mut &[u8]) -> Result<String, codec::Error> {
+	T::decode(input).map(|value| format!("{:?}", value))
+}
+
+fn opaque_decoder<T: Decode>(input: &mut &[u8]) -> Result<String, codec::Error> {
+	let before = *input;
+	T::decode(input)?;
+	Ok(format!("{:?}", before[..before.len() - input.len()].hex_display()))
+}
+
+/// The reason a value could not be decoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+	/// The given type name is not known to the registry.
+	UnknownType(String),
+	/// The value failed to decode.
+	Codec(String),
+	/// The value was decoded, but not all of the bytes were consumed.
+	TrailingBytes(usize),
+}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		match self {
+			Self::UnknownType(ty) => write!(f, "unknown type {}", ty),
+			Self::Codec(e) => write!(f, "{}", e),
+			Self::TrailingBytes(len) => write!(f, "{} trailing bytes", len),
+		}
+	}
+}
+
+impl From<codec::Error> for Error {
+	fn from(e: codec::Error) -> Self {
+		Self::Codec(e.to_string())
+	}
+}
+
+/// Split `input` by `separator`, only at the top level of nesting.
+fn split_top_level(input: &str, separator: char) -> Vec<&str> {
+	let mut parts = vec![];
+	let (mut depth, mut start) = (0, 0);
+	for (i, c) in input.char_indices() {
+		match c {
+			'<' | '(' | '[' => depth += 1,
+			'>' | ')' | ']' => depth -= 1,
+			c if c == separator && depth == 0 => {
+				parts.push(input[start..i].trim());
+				start = i + c.len_utf8();
+			}
+			_ => {}
+		}
+	}
+	parts.push(input[start..].trim());
+	parts.into_iter().filter(|p| !p.is_empty()).collect()
+}
+
+/// The concrete types of type names.
+pub struct TypeRegistry {
+	decoders: BTreeMap<String, Decoder>,
+}
+
+impl Default for TypeRegistry {
+	fn default() -> Self {
+		let mut registry = Self { decoders: Default::default() };
+		registry
+			.register::<bool>(&["bool"])
+			.register::<u8>(&["u8", "Percent"])
+			.register::<u16>(&["u16"])
+			.register::<u32>(&["u32", "Perbill", "Permill", "EraIndex", "SessionIndex"])
+			.register::<u64>(&["u64", "Perquintill", "Weight"])
+			.register::<u128>(&["u128"])
+			.register::<i8>(&["i8"])
+			.register::<i16>(&["i16"])
+			.register::<i32>(&["i32"])
+			.register::<i64>(&["i64"])
+			.register::<i128>(&["i128"])
+			.register::<sp_core::H256>(&["H256"]);
+		registry
+	}
+}
+
+impl TypeRegistry {
+	/// Create a new registry, with only the primitive types registered.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Register `T` as the concrete type of all of the given `names`.
+	///
+	/// The names must match the metadata exactly, e.g. `T::AccountId` or `BalanceOf<T>`.
+	pub fn register<T: Decode + Debug>(&mut self, names: &[&str]) -> &mut Self {
+		for name in names {
+			self.decoders.insert(name.to_string(), decoder::<T>);
+		}
+		self
+	}
+
+	/// Same as [`Self::register`], for types that do not implement `Debug`.
+	///
+	/// The values are still checked to decode as `T`, but are displayed as hex.
+	pub fn register_opaque<T: Decode>(&mut self, names: &[&str]) -> &mut Self {
+		for name in names {
+			self.decoders.insert(name.to_string(), opaque_decoder::<T>);
+		}
+		self
+	}
+
+	/// Decode `len` items of type `item` from `input`, displayed as a list.
+	///
+	/// Bytes are displayed as a single hex string.
+	fn decode_seq(&self, item: &str, len: usize, input: &mut &[u8]) -> Result<String, Error> {
+		if item == "u8" {
+			let bytes = (0..len).map(|_| input.read_byte()).collect::<Result<Vec<_>, _>>()?;
+			return Ok(format!("{:?}", bytes.hex_display()));
+		}
+		let items =
+			(0..len).map(|_| self.decode_from(item, input)).collect::<Result<Vec<_>, _>>()?;
+		Ok(format!("[{}]", items.join(", ")))
+	}
+
+	/// Decode a value of type `ty` from `input`.
+	fn decode_from(&self, ty: &str, input: &mut &[u8]) -> Result<String, Error> {
+		let ty = ty.trim();
+		if let Some(decoder) = self.decoders.get(ty) {
+			return decoder(input).map_err(Into::into);
+		}
+
+		// tuples, including the unit type.
+		if let Some(inner) = ty.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+			let items = split_top_level(inner, ',')
+				.into_iter()
+				.map(|t| self.decode_from(t, input))
+				.collect::<Result<Vec<_>, _>>()?;
+			return Ok(format!("({})", items.join(", ")));
+		}
+
+		// fixed size arrays, e.g. `[u8; 32]`.
+		if let Some(inner) = ty.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+			if let [item, len] = &split_top_level(inner, ';')[..] {
+				let len = len.parse::<usize>().map_err(|_| Error::UnknownType(ty.into()))?;
+				return self.decode_seq(item, len, input);
+			}
+			return Err(Error::UnknownType(ty.into()));
+		}
+
+		// generic containers.
+		if let Some((outer, inner)) = ty.strip_suffix('>').and_then(|t| t.split_once('<')) {
+			let args = split_top_level(inner, ',');
+			return match (outer.trim(), &args[..]) {
+				("Vec" | "VecDeque" | "BTreeSet", [item]) => {
+					let len = <Compact<u32>>::decode(input)?.0;
+					self.decode_seq(item, len as usize, input)
+				}
+				("BTreeMap", [key, value]) => {
+					let len = <Compact<u32>>::decode(input)?.0;
+					let entries = (0..len)
+						.map(|_| {
+							let key = self.decode_from(key, input)?;
+							Ok(format!("{}: {}", key, self.decode_from(value, input)?))
+						})
+						.collect::<Result<Vec<_>, Error>>()?;
+					Ok(format!("{{{}}}", entries.join(", ")))
+				}
+				("Option", [item]) => match input.read_byte()? {
+					0 => Ok("None".into()),
+					1 => Ok(format!("Some({})", self.decode_from(item, input)?)),
+					_ => Err(Error::Codec("invalid Option variant".into())),
+				},
+				("Box", [item]) => self.decode_from(item, input),
+				("Compact", [_]) => Ok(<Compact<u128>>::decode(input)?.0.to_string()),
+				_ => Err(Error::UnknownType(ty.into())),
+			};
+		}
+
+		Err(Error::UnknownType(ty.into()))
+	}
+
+	/// Decode the entire `value` as type `ty`, and display it.
+	pub fn decode(&self, ty: &str, value: &[u8]) -> Result<String, Error> {
+		let mut input = value;
+		let decoded = self.decode_from(ty, &mut input)?;
+		if input.is_empty() {
+			Ok(decoded)
+		} else {
+			Err(Error::TrailingBytes(input.len()))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use codec::Encode;
+
+	#[test]
+	fn split_top_level_works() {
+		assert_eq!(split_top_level("u32, (u8, u16), Vec<(u8, u8)>", ','), vec![
+			"u32",
+			"(u8, u16)",
+			"Vec<(u8, u8)>"
+		]);
+		assert_eq!(split_top_level("[u8; 32]; 4", ';'), vec!["[u8; 32]", "4"]);
+	}
+
+	#[test]
+	fn decode_works() {
+		let mut registry = TypeRegistry::new();
+		registry.register::<u64>(&["T::AccountId"]).register::<u128>(&["BalanceOf<T>"]);
+
+		let value = vec![(1u64, 10u128), (2, 20)].encode();
+		assert_eq!(
+			registry.decode("Vec<(T::AccountId, BalanceOf<T>)>", &value),
+			Ok("[(1, 10), (2, 20)]".into())
+		);
+		assert_eq!(
+			registry.decode("Vec<(T::AccountId, u64)>", &value),
+			Err(Error::TrailingBytes(16))
+		);
+		assert_eq!(registry.decode("Vec<T::AccountId>", &value), Err(Error::TrailingBytes(32)));
+		assert_eq!(registry.decode("Vec<Foo>", &value), Err(Error::UnknownType("Foo".into())));
+
+		assert_eq!(registry.decode("Option<u32>", &Some(5u32).encode()), Ok("Some(5)".into()));
+		assert_eq!(registry.decode("Option<u32>", &[0]), Ok("None".into()));
+		assert_eq!(
+			registry.decode("Option<u32>", &[2]),
+			Err(Error::Codec("invalid Option variant".into()))
+		);
+		assert_eq!(registry.decode("[u8; 4]", &[1, 2, 3, 4]), Ok("0x01020304".into()));
+		assert_eq!(registry.decode("Vec<u8>", &vec![1u8, 2].encode()), Ok("0x0102".into()));
+		assert_eq!(
+			registry.decode("Compact<BalanceOf<T>>", &Compact(1000u128).encode()),
+			Ok("1000".into())
+		);
+		assert_eq!(registry.decode("()", &[]), Ok("()".into()));
+
+		let map: BTreeMap<u32, Vec<u16>> = vec![(1, vec![1, 2]), (2, vec![])].into_iter().collect();
+		assert_eq!(
+			registry.decode("BTreeMap<u32, Vec<u16>>", &map.encode()),
+			Ok("{1: [1, 2], 2: []}".into())
+		);
+		assert!(registry.decode("BTreeMap<u32, Vec<u16>>", &map.encode()[..5]).is_err());
+	}
+
+	#[test]
+	fn opaque_types_are_displayed_as_hex() {
+		#[derive(Decode)]
+		struct Opaque {
+			_inner: u16,
+		}
+
+		let mut registry = TypeRegistry::new();
+		registry.register_opaque::<Opaque>(&["Opaque"]);
+		assert_eq!(registry.decode("(Opaque, u8)", &[1, 2, 3]), Ok("(0x0102, 3)".into()));
+		assert_eq!(registry.decode("Opaque", &[1, 2, 3]), Err(Error::TrailingBytes(1)));
+	}
+}
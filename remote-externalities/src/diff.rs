@@ -0,0 +1,365 @@
+//! Diffing two states, e.g. two snapshots at different blocks, or a snapshot and the state after a
+//! migration.
+//!
+//! Keys are grouped by storage item, i.e. their first 32 bytes. If the metadata of the runtime is
+//! available, the groups are named after the module and storage item (see [`item_names`]).
+//! Otherwise, or for keys that do not belong to any known item, the hex prefix is used.
+//!
+//! Values are decoded with a [`TypeRegistry`], e.g. [`polkadot_types`], and shown in hex if their
+//! type is not known to it or they fail to decode.
+
+use crate::{decode::TypeRegistry, unwrap_decoded, Builder, Hash, HexDisplayExt, KeyPair};
+use frame_metadata::{RuntimeMetadata, RuntimeMetadataPrefixed, StorageEntryType};
+use jsonrpsee_http_client::{HttpClient, HttpConfig};
+use sp_core::hashing::twox_128;
+use std::{
+	collections::BTreeMap,
+	fmt::{Display, Formatter, Result as FmtResult},
+};
+
+/// Maximum number of bytes of a value that are displayed as hex.
+const MAX_DISPLAYED_BYTES: usize = 32;
+
+/// The name and value type of a storage item, as found in the metadata.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ItemInfo {
+	/// Name of the module, i.e. the storage prefix.
+	pub module: String,
+	/// Name of the storage item.
+	pub item: String,
+	/// Name of the type of the values, e.g. `T::BlockNumber`.
+	pub value_type: String,
+}
+
+/// The info of all storage items, keyed by their 32 byte prefix.
+pub type ItemNames = BTreeMap<Vec<u8>, ItemInfo>;
+
+/// Extract the names of all storage items from `metadata`.
+///
+/// Only metadata V12 is supported.
+pub fn item_names(metadata: &RuntimeMetadataPrefixed) -> ItemNames {
+	let modules = match &metadata.1 {
+		RuntimeMetadata::V12(inner) => unwrap_decoded(inner.modules.clone()),
+		_ => panic!("Unsupported metadata version. Please make an issue."),
+	};
+
+	let mut names = ItemNames::new();
+	for storage in modules.into_iter().filter_map(|m| m.storage.map(unwrap_decoded)) {
+		let module = unwrap_decoded(storage.prefix);
+		for entry in unwrap_decoded(storage.entries) {
+			let item = unwrap_decoded(entry.name);
+			let value_type = match entry.ty {
+				StorageEntryType::Plain(value) => value,
+				StorageEntryType::Map { value, .. } => value,
+				StorageEntryType::DoubleMap { value, .. } => value,
+			};
+			let mut prefix = twox_128(module.as_bytes()).to_vec();
+			prefix.extend_from_slice(&twox_128(item.as_bytes()));
+			names.insert(
+				prefix,
+				ItemInfo { module: module.clone(), item, value_type: unwrap_decoded(value_type) },
+			);
+		}
+	}
+	names
+}
+
+/// Fetch the metadata from the node at `uri`, at block `at`, and extract the item names from it.
+pub async fn item_names_from_remote(uri: &str, at: Hash) -> ItemNames {
+	let client =
+		HttpClient::new(uri.to_string(), HttpConfig { max_request_body_size: u32::max_value() })
+			.unwrap();
+	item_names(&Builder::rpc_get_metadata(&client, at).await)
+}
+
+/// A registry of the primitive types, and of the aliases of the generic types of the runtime,
+/// e.g. `T::AccountId` or `BalanceOf<T>`.
+///
+/// The concrete types are assumed to be the ones used in polkadot and kusama. Structs and enums,
+/// e.g. the values of `System::Account` or `Staking::Ledger`, are not known and hence displayed in
+/// hex; register them on top of this to decode them as well.
+pub fn polkadot_types() -> TypeRegistry {
+	let mut registry = TypeRegistry::new();
+	registry
+		.register::<u32>(&["T::BlockNumber", "T::Index"])
+		.register::<u64>(&["T::Moment"])
+		.register::<u128>(&["T::Balance", "BalanceOf<T>", "BalanceOf<T, I>"])
+		.register::<sp_core::crypto::AccountId32>(&["T::AccountId"])
+		.register::<Hash>(&["T::Hash"]);
+	registry
+}
+
+/// The change of a single key.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change {
+	/// The key was added with the given value.
+	Added(Vec<u8>),
+	/// The key, with the given value, was removed.
+	Removed(Vec<u8>),
+	/// The value of the key was changed from the first to the second.
+	Modified(Vec<u8>, Vec<u8>),
+}
+
+/// The changes of a single storage item.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ItemDiff {
+	/// The prefix of the item, i.e. the first 32 bytes of its keys, or the entire key if shorter.
+	pub prefix: Vec<u8>,
+	/// The info of the item, if known.
+	pub info: Option<ItemInfo>,
+	/// Number of added keys.
+	pub added: usize,
+	/// Number of removed keys.
+	pub removed: usize,
+	/// Number of keys with a modified value.
+	pub modified: usize,
+	/// Total size of the keys and values of this item before.
+	pub size_before: usize,
+	/// Total size of the keys and values of this item after.
+	pub size_after: usize,
+	/// All of the changed keys.
+	pub changes: Vec<(Vec<u8>, Change)>,
+}
+
+impl ItemDiff {
+	/// The name of the item, as `Module.Item`, or its hex prefix if not known.
+	pub fn name(&self) -> String {
+		match &self.info {
+			Some(info) => format!("{}.{}", info.module, info.item),
+			None => match std::str::from_utf8(&self.prefix) {
+				// well known keys, such as `:code`.
+				Ok(s) if s.starts_with(':') => s.to_string(),
+				_ => format!("{:?}", self.prefix.hex_display()),
+			},
+		}
+	}
+
+	/// Display `value`, decoded with `registry` if possible.
+	pub fn display_value(&self, value: &[u8], registry: &TypeRegistry) -> String {
+		self.info
+			.as_ref()
+			.and_then(|info| registry.decode(&info.value_type, value).ok())
+			.unwrap_or_else(|| {
+				if value.len() > MAX_DISPLAYED_BYTES {
+					format!(
+						"{:?}.. ({} bytes)",
+						value[..MAX_DISPLAYED_BYTES].hex_display(),
+						value.len()
+					)
+				} else {
+					format!("{:?}", value.hex_display())
+				}
+			})
+	}
+}
+
+impl Display for ItemDiff {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		write!(
+			f,
+			"{}: +{} -{} ~{} ({} -> {} bytes)",
+			self.name(),
+			self.added,
+			self.removed,
+			self.modified,
+			self.size_before,
+			self.size_after,
+		)
+	}
+}
+
+/// The difference between two states.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StateDiff {
+	/// The changed items, sorted by name.
+	pub items: Vec<ItemDiff>,
+}
+
+impl StateDiff {
+	/// If there are no changes at all.
+	pub fn is_empty(&self) -> bool {
+		self.items.is_empty()
+	}
+
+	/// Write all of the changed keys, and their values decoded with `registry`, to `f`.
+	pub fn display_values(
+		&self,
+		f: &mut impl std::fmt::Write,
+		registry: &TypeRegistry,
+	) -> FmtResult {
+		for item in self.items.iter() {
+			writeln!(f, "{}", item)?;
+			for (key, change) in item.changes.iter() {
+				match change {
+					Change::Added(v) => writeln!(
+						f,
+						"  + {:?}: {}",
+						key.hex_display(),
+						item.display_value(v, registry)
+					)?,
+					Change::Removed(v) => writeln!(
+						f,
+						"  - {:?}: {}",
+						key.hex_display(),
+						item.display_value(v, registry)
+					)?,
+					Change::Modified(old, new) => writeln!(
+						f,
+						"  ~ {:?}: {} -> {}",
+						key.hex_display(),
+						item.display_value(old, registry),
+						item.display_value(new, registry),
+					)?,
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Display for StateDiff {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		for item in self.items.iter() {
+			writeln!(f, "{}", item)?;
+		}
+		Ok(())
+	}
+}
+
+/// Compute the difference of `after` compared to `before`, naming the items with `names`.
+pub fn diff(before: &[KeyPair], after: &[KeyPair], names: &ItemNames) -> StateDiff {
+	let before = before.iter().map(|(k, v)| (&k.0, &v.0)).collect::<BTreeMap<_, _>>();
+	let after = after.iter().map(|(k, v)| (&k.0, &v.0)).collect::<BTreeMap<_, _>>();
+
+	fn item_of<'a>(
+		items: &'a mut BTreeMap<Vec<u8>, ItemDiff>,
+		names: &ItemNames,
+		key: &[u8],
+	) -> &'a mut ItemDiff {
+		let prefix = crate::item_prefix(key).to_vec();
+		items.entry(prefix.clone()).or_insert_with(|| ItemDiff {
+			info: names.get(&prefix).cloned(),
+			prefix,
+			..Default::default()
+		})
+	}
+
+	let mut items = BTreeMap::<Vec<u8>, ItemDiff>::new();
+	for (key, value) in before.iter() {
+		let item = item_of(&mut items, names, key);
+		item.size_before += key.len() + value.len();
+		match after.get(key) {
+			None => {
+				item.removed += 1;
+				item.changes.push((key.to_vec(), Change::Removed(value.to_vec())));
+			}
+			Some(new) if new != value => {
+				item.modified += 1;
+				item.changes.push((key.to_vec(), Change::Modified(value.to_vec(), new.to_vec())));
+			}
+			Some(_) => {}
+		}
+	}
+	for (key, value) in after.iter() {
+		let item = item_of(&mut items, names, key);
+		item.size_after += key.len() + value.len();
+		if !before.contains_key(key) {
+			item.added += 1;
+			item.changes.push((key.to_vec(), Change::Added(value.to_vec())));
+		}
+	}
+
+	let mut items = items.into_values().filter(|i| !i.changes.is_empty()).collect::<Vec<_>>();
+	items.iter_mut().for_each(|i| i.changes.sort_by(|a, b| a.0.cmp(&b.0)));
+	items.sort_by_key(|i| i.name());
+	StateDiff { items }
+}
+
+/// Compute the difference of the current state of `ext` compared to `before`.
+///
+/// Useful to check what a migration executed on `ext` has changed.
+pub fn diff_ext(
+	before: &[KeyPair],
+	ext: &mut crate::TestExternalities,
+	names: &ItemNames,
+) -> StateDiff {
+	diff(before, &crate::ext_pairs(ext), names)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_core::storage::{StorageData, StorageKey};
+
+	fn kp(key: &[u8], value: &[u8]) -> KeyPair {
+		(StorageKey(key.to_vec()), StorageData(value.to_vec()))
+	}
+
+	fn names() -> ItemNames {
+		let mut names = ItemNames::new();
+		names.insert(
+			vec![1; 32],
+			ItemInfo { module: "System".into(), item: "Number".into(), value_type: "u32".into() },
+		);
+		names
+	}
+
+	#[test]
+	fn diff_works() {
+		let number = [vec![1; 32], vec![0]].concat();
+		let other = [vec![2; 32], vec![0]].concat();
+		let before = vec![
+			kp(&number, &10u32.to_le_bytes()),
+			kp(&[vec![1; 32], vec![1]].concat(), &[1, 0, 0, 0]),
+			kp(&other, &[1]),
+			kp(b":code", &[0]),
+		];
+		let after = vec![
+			kp(&number, &11u32.to_le_bytes()),
+			kp(&[vec![1; 32], vec![1]].concat(), &[1, 0, 0, 0]),
+			kp(&[vec![2; 32], vec![1]].concat(), &[2]),
+			kp(b":code", &[0]),
+		];
+
+		let diff = diff(&before, &after, &names());
+		assert_eq!(diff.items.len(), 2);
+
+		let system = &diff.items[1];
+		assert_eq!(system.name(), "System.Number");
+		assert_eq!((system.added, system.removed, system.modified), (0, 0, 1));
+		assert_eq!(
+			system.changes,
+			vec![(number, Change::Modified(vec![10, 0, 0, 0], vec![11, 0, 0, 0]))]
+		);
+		assert_eq!(system.display_value(&[11, 0, 0, 0], &polkadot_types()), "11");
+		assert_eq!(system.display_value(&[11, 0, 0], &polkadot_types()), "0x0b0000");
+
+		let unknown = &diff.items[0];
+		assert_eq!(unknown.name(), format!("{:?}", vec![2u8; 32].hex_display()));
+		assert_eq!((unknown.added, unknown.removed, unknown.modified), (1, 1, 0));
+		assert_eq!(unknown.size_before, 34);
+		assert_eq!(unknown.size_after, 34);
+
+		let mut values = String::new();
+		diff.display_values(&mut values, &polkadot_types()).unwrap();
+		assert!(values.contains("~ 0x"));
+		assert!(values.contains("10 -> 11"));
+	}
+
+	#[test]
+	fn diff_ext_works() {
+		let before = vec![kp(&[vec![1; 32], vec![0]].concat(), &[1, 0, 0, 0]), kp(b":code", &[0])];
+		let mut ext = crate::ext_from_pairs(before.clone());
+		assert!(diff_ext(&before, &mut ext, &names()).is_empty());
+
+		ext.execute_with(|| {
+			sp_io::storage::set(&[vec![1; 32], vec![0]].concat(), &[2, 0, 0, 0]);
+			sp_io::storage::clear(b":code");
+		});
+		let diff = diff_ext(&before, &mut ext, &names());
+		assert_eq!(diff.items.len(), 2);
+		assert_eq!(diff.items[0].name(), ":code");
+		assert_eq!(diff.items[0].removed, 1);
+		assert_eq!(diff.items[1].modified, 1);
+	}
+}
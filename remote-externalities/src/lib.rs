@@ -129,24 +129,138 @@
 //!         .build()
 //!         .execute_with(|| assert_eq!(<pallet_staking::Module<Runtime>>::validator_count(), 400));
 //! }
+//! ```
+//!
+//! ### Cache
+//!
+//! Scraping a chain is slow, hence the builder can store the downloaded keys in a cache file (see
+//! [`CacheMode`]). The cache files are stored in the directory given to [`Builder::cache_dir`]. If
+//! not set, the `REMOTE_EXT_CACHE_DIR` environment variable is used, and if that is also not set,
+//! the current directory. Pointing different tools to the same directory lets them share one
+//! snapshot store.
+//!
+//! Each cache file starts with a header recording the chain, block hash, state root, spec version
+//! and key filters it was created with, alongside a checksum of the data (see [`snapshot`]).
+//! Loading a cache that is corrupt, or was created for another chain or key filters, fails with a
+//! clear error, after which the cache is re-created. Full-chain caches can be compressed via
+//! [`Builder::compression`].
+//!
+//! Existing cache files can be inspected with [`cache::list`] and cleaned up with
+//! [`cache::prune`].
+//!
+//! ```ignore
+//! use remote_externalities::{cache::{self, PruneMode}, default_cache_dir};
+//!
+//! for info in cache::list(default_cache_dir()).unwrap() {
+//!     println!("{}", info);
+//! }
+//!
+//! // only keep the 5 most recent snapshots.
+//! cache::prune(default_cache_dir(), PruneMode::KeepLatest(5)).unwrap();
+//! ```
+//!
+//! ### Scraping
+//!
+//! The state is downloaded with `state_getKeysPaged` and `state_queryStorageAt`, one page of keys
+//! at a time, over a number of parallel connections (see [`Builder::connections`]). The work is
+//! split per module, using the storage prefixes found in the metadata, and storage items that span
+//! more than one page are further split by the first byte after their prefix. Keys that do not
+//! belong to any module in the metadata, e.g. those of removed modules, are downloaded by a final
+//! task that walks the gaps between the module prefixes and the well-known `:`-prefixed keys,
+//! jumping over each of them with a single page. The content of child tries is not downloaded,
+//! hence the keys of child tries are only used for the state root check, and left out of the built
+//! externalities.
+//!
+//! Instead of the entire chain, only some modules ([`Builder::module`]), storage items
+//! ([`Builder::storage_item`]) or raw prefixes ([`Builder::raw_prefix`]) can be scraped. Any of
+//! these can also be left out via [`Builder::exclude`]. For example, the following only downloads
+//! the staking items needed to run an election:
+//!
+//! ```ignore
+//! Builder::new()
+//!     .storage_item("Staking", "Nominators")
+//!     .storage_item("Staking", "Validators")
+//!     .storage_item("Staking", "Ledger")
+//!     .storage_item("Staking", "Bonded")
+//!     .storage_item("Staking", "SlashingSpans")
+//!     .build()
+//!     .await;
+//! ```
+//!
+//! ### State files
+//!
+//! Instead of a remote node, the state can also be read from a chain-spec file with a raw genesis,
+//! such as the output of `build-spec --raw` or `export-state`, via [`Builder::from_file`]. The
+//! filters and injections work the same, which allows testing against states produced locally.
+//!
+//! Conversely, any externalities, e.g. after executing a migration on them, can be written to a
+//! snapshot via [`save_snapshot`]. Such a snapshot can again be used with [`Builder::from_file`],
+//! or compared to the original state (see below).
+//!
+//! ### Diffing
+//!
+//! The [`diff`] module computes the added, removed and modified keys between two states, grouped by
+//! storage item, e.g. between two snapshots, or between a snapshot and the externalities after a
+//! migration ([`diff::diff_ext`]). Values are decoded with a [`decode::TypeRegistry`], and shown
+//! in hex if their type is not known to it. The same is available as the `snapshot-diff` binary:
+//!
+//! ```ignore
+//! cargo run --features cli --bin snapshot-diff -- old.bin new.bin --uri http://localhost:9933 -v
+//! ```
 
 use std::{
+	collections::{BTreeMap, BTreeSet, VecDeque},
 	fs,
 	path::{Path, PathBuf},
 };
 use std::fmt::{Debug, Formatter, Result as FmtResult};
 use log::*;
+use futures::stream::{FuturesUnordered, StreamExt};
 use sp_core::{hashing::twox_128};
 pub use sp_io::TestExternalities;
-use sp_core::storage::{StorageKey, StorageData};
+use sp_core::storage::{well_known_keys, StorageChangeSet, StorageKey, StorageData};
 use jsonrpsee_http_client::{HttpClient, HttpConfig};
 use jsonrpsee_types::jsonrpc::{Params, to_value as to_json_value};
+use serde::Deserialize;
+use codec::Decode;
+use frame_metadata::{DecodeDifferent, RuntimeMetadata, RuntimeMetadataPrefixed};
+
+/// Inspection and management of the cache files.
+pub mod cache;
+/// Decoding storage values by their type names.
+pub mod decode;
+/// Diffing two states.
+pub mod diff;
+/// The on-disk format of the cache files.
+pub mod snapshot;
+
+pub use snapshot::{Compression, SnapshotHeader};
 
 type Hash = sp_core::H256;
 type KeyPair = (StorageKey, StorageData);
 
 const LOG_TARGET: &'static str = "remote-ext";
 
+/// Maximum number of missing prefixes reported upon a state root mismatch.
+const MAX_MISSING_PREFIX_SAMPLES: usize = 16;
+
+/// Number of keys requested per `state_getKeysPaged` call. This is the maximum allowed by
+/// substrate nodes.
+const PAGE_SIZE: u32 = 1000;
+
+/// Default number of parallel connections used while scraping.
+const DEFAULT_CONNECTIONS: usize = 4;
+
+/// The environment variable that can be used to set the default cache directory.
+pub const CACHE_DIR_ENV: &'static str = "REMOTE_EXT_CACHE_DIR";
+
+/// The default directory of the cache files.
+///
+/// This is the value of [`CACHE_DIR_ENV`] if set, else the current directory.
+pub fn default_cache_dir() -> PathBuf {
+	std::env::var_os(CACHE_DIR_ENV).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."))
+}
+
 /// Struct for better hex printing of slice types.
 pub struct HexSlice<'a>(&'a [u8]);
 
@@ -164,7 +278,7 @@ impl Debug for HexSlice<'_> {
 	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
 		write!(f, "0x")?;
 		for byte in self.0 {
-			write!(f, "{:x}", byte)?;
+			write!(f, "{:02x}", byte)?;
 		}
 		Ok(())
 	}
@@ -192,24 +306,248 @@ pub enum CacheMode {
 	None,
 }
 
+impl std::str::FromStr for CacheMode {
+	type Err = &'static str;
+
+	/// Parse `none`, `use` (use the cache if it is there, else create it) or `force`.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"none" => Ok(Self::None),
+			"use" => Ok(Self::UseElseCreate),
+			"force" => Ok(Self::ForceUpdate),
+			_ => Err("cache mode must be one of `none`, `use` or `force`"),
+		}
+	}
+}
+
 /// The name of the cache file configuration.
 pub enum CacheName {
-	/// It will be {chain_name},{hash},{modules?}.bin
+	/// It will be {chain_name},{hash},{filters?}.bin, where each filter is rendered as per the
+	/// `Display` implementation of [`KeyFilter`], and exclusions are prefixed with `-`.
 	Auto,
 	/// Forced to the given file name.
 	Forced(String),
 }
 
+/// A filter on the keys that are scraped.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum KeyFilter {
+	/// All of the storage items of a module, e.g. `Staking`.
+	Module(String),
+	/// A single storage item of a module, e.g. `Staking` and `Nominators`.
+	StorageItem(String, String),
+	/// All keys that start with the given raw bytes.
+	RawPrefix(Vec<u8>),
+}
+
+impl KeyFilter {
+	/// The prefix of all of the keys that match this filter.
+	pub fn prefix(&self) -> Vec<u8> {
+		match self {
+			Self::Module(module) => twox_128(module.as_bytes()).to_vec(),
+			Self::StorageItem(module, item) => {
+				let mut prefix = twox_128(module.as_bytes()).to_vec();
+				prefix.extend_from_slice(&twox_128(item.as_bytes()));
+				prefix
+			}
+			Self::RawPrefix(prefix) => prefix.clone(),
+		}
+	}
+}
+
+impl std::fmt::Display for KeyFilter {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		match self {
+			Self::Module(module) => write!(f, "{}", module),
+			Self::StorageItem(module, item) => write!(f, "{}.{}", module, item),
+			Self::RawPrefix(prefix) => {
+				write!(f, "0x")?;
+				prefix.iter().try_for_each(|b| write!(f, "{:02x}", b))
+			}
+		}
+	}
+}
+
+/// Unwrap the decoded variant of metadata that was read from a node.
+pub(crate) fn unwrap_decoded<B, O>(input: DecodeDifferent<B, O>) -> O {
+	match input {
+		DecodeDifferent::Decoded(o) => o,
+		_ => panic!("metadata is always decoded when read from a node; qed"),
+	}
+}
+
+/// The keys that [`TestExternalities::new_empty`] seeds into every state.
+const SEEDED_KEYS: [&[u8]; 2] = [b":heappages", b":code"];
+
+/// Build externalities that hold exactly `pairs`, including the keys of child tries.
+///
+/// The pairs are written to the backend directly, as [`TestExternalities::new`] would overwrite
+/// `:code` and `:heappages`, and refuses the keys of child tries. The seeded `:code` and
+/// `:heappages` of [`TestExternalities::new_empty`] are removed, unless they are part of `pairs`.
+///
+/// The keys of child tries only hold the root of the child trie, not its content, so this state
+/// can verify the storage root, but reading a child trie fails.
+fn raw_ext_from_pairs(pairs: Vec<KeyPair>) -> TestExternalities {
+	let mut top = pairs.into_iter().map(|(k, v)| (k.0, Some(v.0))).collect::<BTreeMap<_, _>>();
+	for seeded in SEEDED_KEYS.iter() {
+		top.entry(seeded.to_vec()).or_insert(None);
+	}
+
+	let mut ext = TestExternalities::new_empty();
+	ext.backend.insert(vec![(None, top.into_iter().collect())]);
+	ext
+}
+
+/// The keys of all child tries in `pairs`.
+fn child_trie_keys(pairs: &[KeyPair]) -> Vec<Vec<u8>> {
+	pairs
+		.iter()
+		.filter(|(k, _)| well_known_keys::is_child_storage_key(&k.0))
+		.map(|(k, _)| k.0.clone())
+		.collect()
+}
+
+/// Remove the keys of the given child tries from the top trie of `ext`.
+///
+/// The content of child tries is not scraped, hence their keys, i.e. their roots, would point to
+/// missing tries.
+fn remove_child_tries(ext: &mut TestExternalities, children: Vec<Vec<u8>>) {
+	if !children.is_empty() {
+		warn!(
+			target: LOG_TARGET,
+			"leaving out {} child tries, as they are not scraped.",
+			children.len()
+		);
+		ext.backend.insert(vec![(None, children.into_iter().map(|k| (k, None)).collect())]);
+	}
+}
+
+/// Build externalities that hold exactly `pairs`.
+///
+/// `:code` and `:heappages` are only present if part of `pairs`. The keys of child tries (i.e.
+/// `:child_storage:*`) are left out, since only their roots are known, not their content.
+pub fn ext_from_pairs(pairs: Vec<KeyPair>) -> TestExternalities {
+	let children = child_trie_keys(&pairs);
+	let mut ext = raw_ext_from_pairs(pairs);
+	remove_child_tries(&mut ext, children);
+	ext
+}
+
+/// All of the key-value pairs of `ext`, including the changes made via `execute_with`, sorted by
+/// key.
+pub fn ext_pairs(ext: &mut TestExternalities) -> Vec<KeyPair> {
+	ext.execute_with(|| {
+		let mut pairs = vec![];
+		let mut key = vec![];
+		while let Some(next) = sp_io::storage::next_key(&key) {
+			let value = sp_io::storage::get(&next).expect("key was just iterated; qed");
+			pairs.push((StorageKey(next.clone()), StorageData(value)));
+			key = next;
+		}
+		pairs
+	})
+}
+
+/// Save the state of `ext`, including the changes made via `execute_with`, as a snapshot at
+/// `path`.
+///
+/// The header of the snapshot holds the storage root of `ext`, but the chain, block hash and spec
+/// version are not known and left empty. Use [`save_snapshot_with`] to set them.
+pub fn save_snapshot<P: AsRef<Path>>(
+	ext: &mut TestExternalities,
+	path: P,
+) -> Result<SnapshotHeader, snapshot::Error> {
+	let header = SnapshotHeader::new(
+		Default::default(),
+		Default::default(),
+		Default::default(),
+		Default::default(),
+		Default::default(),
+		Compression::None,
+	);
+	save_snapshot_with(ext, path, header)
+}
+
+/// Same as [`save_snapshot`], but with the given `header`, e.g. the one of the snapshot that `ext`
+/// was originally built from. The state root of `header` is overwritten with that of `ext`.
+pub fn save_snapshot_with<P: AsRef<Path>>(
+	ext: &mut TestExternalities,
+	path: P,
+	mut header: SnapshotHeader,
+) -> Result<SnapshotHeader, snapshot::Error> {
+	header.state_root = Hash::from_slice(&ext.execute_with(sp_io::storage::root));
+	snapshot::save(path, header, &ext_pairs(ext))
+}
+
+/// Compare the storage root of `ext` with `expected`, returning the computed root either way.
+fn verify_state_root(ext: &mut TestExternalities, expected: Hash) -> Result<Hash, Hash> {
+	let computed = Hash::from_slice(&ext.execute_with(sp_io::storage::root));
+	if computed == expected {
+		Ok(computed)
+	} else {
+		Err(computed)
+	}
+}
+
+/// The prefix of the storage item of `key`, i.e. the first 32 bytes (`twox128(module) ++
+/// twox128(item)`), or the whole key if shorter.
+fn item_prefix(key: &[u8]) -> &[u8] {
+	&key[..key.len().min(32)]
+}
+
+/// The parts of a chain-spec, or the output of `export-state`, that are needed to build the state.
+#[derive(Deserialize)]
+struct ChainSpec {
+	genesis: Genesis,
+}
+
+#[derive(Deserialize)]
+struct Genesis {
+	raw: Option<RawGenesis>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawGenesis {
+	top: BTreeMap<StorageKey, StorageData>,
+	#[serde(default)]
+	children_default: BTreeMap<StorageKey, serde_json::Value>,
+}
+
+/// A unit of work while scraping: all keys under `prefix` that come after `start_key`, except
+/// those under any of the `skip` prefixes.
+#[derive(Clone, Debug)]
+struct ScrapeTask {
+	/// Human readable label, used for logging.
+	label: String,
+	prefix: Vec<u8>,
+	start_key: Option<StorageKey>,
+	/// Prefixes that are scraped by other tasks.
+	skip: Vec<Vec<u8>>,
+}
+
+impl ScrapeTask {
+	fn new(label: String, prefix: Vec<u8>) -> Self {
+		Self { label, prefix, start_key: None, skip: vec![] }
+	}
+}
+
 /// Builder for remote-externalities.
 pub struct Builder {
 	at: Option<Hash>,
 	uri: String,
 	inject: Vec<KeyPair>,
-	module_filter: Vec<String>,
+	filters: Vec<KeyFilter>,
+	excludes: Vec<KeyFilter>,
 	cache_config: CacheMode,
 	cache_name_config: CacheName,
-	client: Option<HttpClient>,
+	cache_dir: PathBuf,
+	compression: Compression,
+	connections: usize,
+	clients: Vec<HttpClient>,
 	chain: String,
+	state_file: Option<PathBuf>,
+	keep_child_roots: bool,
 }
 
 impl Default for Builder {
@@ -218,11 +556,17 @@ impl Default for Builder {
 			uri: "http://localhost:9933".into(),
 			at: Default::default(),
 			inject: Default::default(),
-			module_filter: Default::default(),
+			filters: Default::default(),
+			excludes: Default::default(),
 			cache_config: CacheMode::None,
 			cache_name_config: CacheName::Auto,
-			client: None,
+			cache_dir: default_cache_dir(),
+			compression: Compression::None,
+			connections: DEFAULT_CONNECTIONS,
+			clients: Default::default(),
 			chain: "UNSET".into(),
+			state_file: None,
+			keep_child_roots: false,
 		}
 	}
 }
@@ -238,18 +582,114 @@ impl Builder {
 		jsonrpsee_types::jsonrpc::from_value(json_value).unwrap()
 	}
 
-	/// Relay the request to `state_getPairs` rpc endpoint.
+	/// Relay the request to `state_getKeysPaged` rpc endpoint, using `client`.
 	///
-	/// Note that this is an unsafe RPC.
-	async fn rpc_get_pairs(&self, prefix: StorageKey, at: Hash) -> Vec<KeyPair> {
+	/// Returns at most `count` keys under `prefix` that come strictly after `start_key`.
+	async fn rpc_get_keys_paged(
+		client: &HttpClient,
+		prefix: StorageKey,
+		count: u32,
+		start_key: Option<StorageKey>,
+		at: Hash,
+	) -> Vec<StorageKey> {
 		let serialized_prefix = to_json_value(prefix).expect("StorageKey serialization infallible");
+		let count = to_json_value(count).expect("u32 serialization infallible");
+		let start_key = to_json_value(start_key).expect("StorageKey serialization infallible");
+		let at = to_json_value(at).expect("Block hash serialization infallible");
+		let json_value = client
+			.request(
+				"state_getKeysPaged",
+				Params::Array(vec![serialized_prefix, count, start_key, at]),
+			)
+			.await
+			.expect("Storage state_getKeysPaged failed");
+		jsonrpsee_types::jsonrpc::from_value(json_value).unwrap()
+	}
+
+	/// Relay the request to `state_queryStorageAt` rpc endpoint, using `client`.
+	///
+	/// Returns the values of all of the given keys that exist.
+	async fn rpc_query_storage_at(
+		client: &HttpClient,
+		keys: Vec<StorageKey>,
+		at: Hash,
+	) -> Vec<KeyPair> {
+		let serialized_keys = to_json_value(keys).expect("StorageKey serialization infallible");
+		let at = to_json_value(at).expect("Block hash serialization infallible");
+		let json_value = client
+			.request("state_queryStorageAt", Params::Array(vec![serialized_keys, at]))
+			.await
+			.expect("Storage state_queryStorageAt failed");
+		let change_sets: Vec<StorageChangeSet<Hash>> =
+			jsonrpsee_types::jsonrpc::from_value(json_value).unwrap();
+		change_sets
+			.into_iter()
+			.flat_map(|set| set.changes)
+			.filter_map(|(k, maybe_v)| maybe_v.map(|v| (k, v)))
+			.collect()
+	}
+
+	/// Relay the request to `state_getMetadata` rpc endpoint, using `client`.
+	pub(crate) async fn rpc_get_metadata(client: &HttpClient, at: Hash) -> RuntimeMetadataPrefixed {
+		let at = to_json_value(at).expect("Block hash serialization infallible");
+		let json_value = client
+			.request("state_getMetadata", Params::Array(vec![at]))
+			.await
+			.expect("state_getMetadata failed");
+		let raw: sp_core::Bytes = jsonrpsee_types::jsonrpc::from_value(json_value).unwrap();
+		RuntimeMetadataPrefixed::decode(&mut &*raw.0).expect("Runtime Metadata failed to decode")
+	}
+
+	/// Get the storage prefix and name of all of the modules that have storage.
+	async fn rpc_get_module_prefixes(&self, at: Hash) -> Vec<(String, Vec<u8>)> {
+		let prefixed = Self::rpc_get_metadata(self.rpc_client(), at).await;
+		if let RuntimeMetadata::V12(inner) = prefixed.1 {
+			unwrap_decoded(inner.modules)
+				.into_iter()
+				.filter_map(|module| module.storage.map(unwrap_decoded))
+				.map(|storage| {
+					let prefix = unwrap_decoded(storage.prefix);
+					let hashed = twox_128(prefix.as_bytes()).to_vec();
+					(prefix, hashed)
+				})
+				.collect()
+		} else {
+			panic!("Unsupported metadata version. Please make an issue.")
+		}
+	}
+
+	/// Get the state root of the block at `at`.
+	async fn rpc_get_state_root(&self, at: Hash) -> Hash {
+		#[derive(Deserialize)]
+		#[serde(rename_all = "camelCase")]
+		struct Header {
+			state_root: Hash,
+		}
+
 		let at = to_json_value(at).expect("Block hash serialization infallible");
 		let json_value = self
 			.rpc_client()
-			.request("state_getPairs", Params::Array(vec![serialized_prefix, at]))
+			.request("chain_getHeader", Params::Array(vec![at]))
 			.await
-			.expect("Storage state_getPairs failed");
-		jsonrpsee_types::jsonrpc::from_value(json_value).unwrap()
+			.expect("chain_getHeader failed");
+		jsonrpsee_types::jsonrpc::from_value::<Header>(json_value).unwrap().state_root
+	}
+
+	/// Get the runtime spec version at `at`.
+	async fn rpc_get_spec_version(&self, at: Hash) -> u32 {
+		#[derive(Deserialize)]
+		#[serde(rename_all = "camelCase")]
+		struct RuntimeVersion {
+			spec_version: u32,
+		}
+
+		let at = to_json_value(at).expect("Block hash serialization infallible");
+		let json_value = self
+			.rpc_client()
+			.request("state_getRuntimeVersion", Params::Array(vec![at]))
+			.await
+			.expect("state_getRuntimeVersion failed");
+		jsonrpsee_types::jsonrpc::from_value::<RuntimeVersion>(json_value).unwrap().spec_version
 	}
 
 	/// Get the chain name.
@@ -263,7 +703,7 @@ impl Builder {
 	}
 
 	fn rpc_client(&self) -> &HttpClient {
-		self.client.as_ref().expect("Client initialized after `build`; qed")
+		self.clients.first().expect("Client initialized after `build`; qed")
 	}
 }
 
@@ -272,44 +712,88 @@ impl Builder {
 	/// The file name associated with this scrape.
 	fn final_cache_name(&self) -> String {
 		match &self.cache_name_config {
-			CacheName::Auto => {
-				format!("{},{:?},{}.bin", self.chain, self.final_at(), self.module_filter.join(","))
-			}
+			CacheName::Auto => format!(
+				"{},{:?},{}.bin",
+				self.chain,
+				self.final_at(),
+				self.filter_names().join(",")
+			),
 			CacheName::Forced(name) => name.clone(),
 		}
 	}
 
-	/// Directory at which to create the cache. Not configurable for now.
-	// TODO
-	fn cache_dir() -> &'static str {
-		"."
+	/// The names of all of the filters, as stored in the cache. Exclusions are prefixed with `-`.
+	fn filter_names(&self) -> Vec<String> {
+		self.filters
+			.iter()
+			.map(|f| f.to_string())
+			.chain(self.excludes.iter().map(|f| format!("-{}", f)))
+			.collect()
 	}
 
 	/// The final path of the cache.
 	fn cache_path(&self) -> PathBuf {
-		Path::new(Self::cache_dir()).join(self.final_cache_name())
+		self.cache_dir.join(self.final_cache_name())
 	}
 
 	/// Save the given data as cache.
-	fn save_cache(&self, data: &[KeyPair]) {
-		let bdata = bincode::serialize(data).unwrap();
+	async fn save_cache(&self, data: &[KeyPair]) {
+		let at = self.final_at();
+		let header = SnapshotHeader::new(
+			self.chain.clone(),
+			at,
+			self.rpc_get_state_root(at).await,
+			self.rpc_get_spec_version(at).await,
+			self.filter_names(),
+			self.compression,
+		);
 		let path = self.cache_path();
 		info!(target: LOG_TARGET, "writing to cache file {:?}", path);
-		fs::write(path, bdata).unwrap();
+		fs::create_dir_all(&self.cache_dir).unwrap();
+		snapshot::save(path, header, data).unwrap();
 	}
 
-	/// Try and initialize `Self` from cache
-	fn try_scrape_cached(&self) -> Result<Vec<KeyPair>, &'static str> {
+	/// Try and initialize `Self` from cache.
+	///
+	/// The header of the cache is checked against the chain and key filters of `self`, and also
+	/// against the block hash if `check_at` is set.
+	fn try_scrape_cached(
+		&self,
+		check_at: bool,
+	) -> Result<(SnapshotHeader, Vec<KeyPair>), snapshot::Error> {
 		info!(
 			target: LOG_TARGET,
 			"scraping keypairs from cache {:?} @ {:?}",
 			self.cache_path(),
 			self.final_at()
 		);
-		let path = self.cache_path();
-		fs::read(path)
-			.map_err(|_| "failed to read cache")
-			.and_then(|b| bincode::deserialize(&b[..]).map_err(|_| "failed to decode cache"))
+		let (header, data) = snapshot::load(self.cache_path())?;
+		let mismatch = |field, expected: String, got: String| {
+			Err(snapshot::Error::HeaderMismatch { field, expected, got })
+		};
+
+		if header.chain != self.chain {
+			return mismatch("chain", self.chain.clone(), header.chain);
+		}
+		if check_at && header.at != self.final_at() {
+			let (expected, got) = (format!("{:?}", self.final_at()), format!("{:?}", header.at));
+			return mismatch("block hash", expected, got);
+		}
+		if header.filters != self.filter_names() {
+			return mismatch("filters", self.filter_names().join(","), header.filters.join(","));
+		}
+
+		info!(
+			target: LOG_TARGET,
+			"loaded {} keys from cache of {} @ {:?} (spec version {}, state root {:?}).",
+			header.key_count,
+			header.chain,
+			header.at,
+			header.spec_version,
+			header.state_root,
+		);
+
+		Ok((header, data))
 	}
 
 	/// Get the final `at` that shall be used.
@@ -324,45 +808,324 @@ impl Builder {
 		let at = self.final_at();
 		info!(target: LOG_TARGET, "scraping keypairs from remote node {} @ {:?}", self.uri, at);
 
-		let mut keys_and_values = if self.module_filter.len() > 0 {
-			let mut filtered_kv = vec![];
-			for f in self.module_filter.iter() {
-				let hashed_prefix = StorageKey(twox_128(f.as_bytes()).to_vec());
-				let module_kv = self.rpc_get_pairs(hashed_prefix.clone(), at).await;
+		let tasks = if self.filters.len() > 0 {
+			self.filters
+				.iter()
+				.map(|f| ScrapeTask::new(f.to_string(), f.prefix()))
+				.collect::<Vec<_>>()
+		} else {
+			info!(target: LOG_TARGET, "downloading data for all modules.");
+			let mut tasks = self
+				.rpc_get_module_prefixes(at)
+				.await
+				.into_iter()
+				.map(|(name, prefix)| ScrapeTask::new(name, prefix))
+				.collect::<Vec<_>>();
+			// well known keys, such as `:code`.
+			tasks.push(ScrapeTask::new("well-known".into(), b":".to_vec()));
+			// all keys in the gaps around the above, e.g. of removed modules.
+			let skip = tasks.iter().map(|t| t.prefix.clone()).collect::<Vec<_>>();
+			tasks.push(ScrapeTask { skip, ..ScrapeTask::new("unknown".into(), vec![]) });
+			tasks
+		};
+
+		let mut keys_and_values = self.scrape_tasks(tasks, at).await;
+
+		// concat any custom key values.
+		keys_and_values.extend(self.inject.clone());
+		keys_and_values
+	}
+
+	/// Scrape all of the given tasks, distributed over all of the clients.
+	///
+	/// Each idle client is handed the next task of a shared queue. The sub-tasks of a split item
+	/// are pushed back to the queue, so scraping only ends once the queue is empty and no task is
+	/// in flight anymore.
+	async fn scrape_tasks(&self, tasks: Vec<ScrapeTask>, at: Hash) -> Vec<KeyPair> {
+		let excludes = self.excludes.iter().map(|f| f.prefix()).collect::<Vec<_>>();
+		let excludes = &excludes;
+		let mut total_tasks = tasks.len();
+		let mut done_tasks = 0usize;
+		let mut queue = tasks.into_iter().collect::<VecDeque<_>>();
+		let mut idle = self.clients.iter().collect::<Vec<_>>();
+		let mut in_flight = FuturesUnordered::new();
+		let mut keys_and_values = Vec::<KeyPair>::new();
+
+		loop {
+			while !idle.is_empty() && !queue.is_empty() {
+				let client = idle.pop().expect("idle is not empty; qed");
+				let task = queue.pop_front().expect("queue is not empty; qed");
+				in_flight.push(async move {
+					let label = task.label.clone();
+					(client, label, Self::scrape_task(client, task, excludes, at).await)
+				});
+			}
+
+			let (client, label, (kv, sub_tasks)) = match in_flight.next().await {
+				Some(done) => done,
+				None => break,
+			};
+			idle.push(client);
+			total_tasks += sub_tasks.len();
+			done_tasks += 1;
+			queue.extend(sub_tasks);
+			keys_and_values.extend(kv);
+			info!(
+				target: LOG_TARGET,
+				"[{}/{}] downloaded data for {} (total keys: {}).",
+				done_tasks,
+				total_tasks,
+				label,
+				keys_and_values.len(),
+			);
+		}
+
+		// a split item might have been partially downloaded in a previous page as well.
+		keys_and_values.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+		keys_and_values.dedup_by(|a, b| a.0 == b.0);
+		keys_and_values
+	}
+
+	/// Scrape a single task, using `client`.
+	///
+	/// If a single storage item with more than one page of keys is detected, its keys are split
+	/// into one sub-task per first byte after the item prefix, and the rest of the current task is
+	/// also returned as a sub-task, so that they can be fetched in parallel. This also applies to
+	/// tasks of a single storage item, but not to the sub-tasks of a split item.
+	///
+	/// Keys that start with any of the `excludes` prefixes, or the `skip` prefixes of the task, are
+	/// skipped, without fetching their values.
+	async fn scrape_task(
+		client: &HttpClient,
+		task: ScrapeTask,
+		excludes: &[Vec<u8>],
+		at: Hash,
+	) -> (Vec<KeyPair>, Vec<ScrapeTask>) {
+		let ScrapeTask { label, prefix, mut start_key, skip } = task;
+		let excluded_by = |key: &StorageKey| {
+			excludes.iter().chain(skip.iter()).find(|p| key.0.starts_with(p)).cloned()
+		};
+		let mut keys_and_values = vec![];
+		loop {
+			let page = Self::rpc_get_keys_paged(
+				client,
+				StorageKey(prefix.clone()),
+				PAGE_SIZE,
+				start_key.clone(),
+				at,
+			)
+			.await;
+			let page_len = page.len();
+			let last_key = page.last().cloned();
+
+			// if the page ends within an excluded range, jump right over it.
+			let last_excluded = last_key.as_ref().and_then(excluded_by);
+			let next_start_key = match last_excluded {
+				Some(ref excluded_prefix) => {
+					let mut skip = excluded_prefix.clone();
+					skip.extend_from_slice(&[u8::max_value(); 64]);
+					Some(StorageKey(skip))
+				}
+				None => last_key.clone(),
+			};
+			let is_full = page_len == PAGE_SIZE as usize;
+			let page = page.into_iter().filter(|k| excluded_by(k).is_none()).collect::<Vec<_>>();
+
+			let first_item = page.first().map(|k| item_prefix(&k.0).to_vec());
+			let last_item = last_key.as_ref().map(|k| item_prefix(&k.0).to_vec());
+			match (first_item, last_item) {
+				(Some(first), Some(last))
+					if is_full &&
+						last_excluded.is_none() &&
+						first == last && first.len() == 32 &&
+						prefix.len() <= 32 =>
+				{
+					// the entire page belongs to one (big) item.
+					debug!(
+						target: LOG_TARGET,
+						"splitting item {:?} of {}",
+						first.hex_display(),
+						label
+					);
+					// the item prefix itself might also be a key.
+					let exact = page.into_iter().filter(|k| k.0 == first).collect::<Vec<_>>();
+					if !exact.is_empty() {
+						keys_and_values.extend(Self::rpc_query_storage_at(client, exact, at).await);
+					}
+
+					let mut sub_tasks = (0..=u8::max_value())
+						.map(|b| {
+							let mut sub_prefix = first.clone();
+							sub_prefix.push(b);
+							ScrapeTask::new(label.clone(), sub_prefix)
+						})
+						.collect::<Vec<_>>();
+					// unless the task is exactly this item, the keys after it are still to scrape.
+					if prefix.len() < 32 {
+						let mut skip = first;
+						skip.extend_from_slice(&[u8::max_value(); 64]);
+						let start_key = Some(StorageKey(skip));
+						sub_tasks.push(ScrapeTask { label, prefix, start_key, skip });
+					}
+					return (keys_and_values, sub_tasks);
+				}
+				_ => {}
+			}
+
+			if !page.is_empty() {
+				keys_and_values.extend(Self::rpc_query_storage_at(client, page, at).await);
+			}
+			if !is_full {
+				break;
+			}
+			start_key = next_start_key;
+		}
+
+		trace!(target: LOG_TARGET, "downloaded {} keys of {}", keys_and_values.len(), label);
+		(keys_and_values, vec![])
+	}
+
+	/// If the storage root of the built externalities should be checked.
+	fn should_check_state_root(&self) -> bool {
+		self.state_file.is_none() &&
+			self.filters.is_empty() &&
+			self.excludes.is_empty() &&
+			self.inject.is_empty()
+	}
+
+	/// Check `key` against the filters and exclusions of `self`.
+	fn is_wanted(&self, key: &[u8]) -> bool {
+		(self.filters.is_empty() || self.filters.iter().any(|f| key.starts_with(&f.prefix()))) &&
+			!self.excludes.iter().any(|f| key.starts_with(&f.prefix()))
+	}
+
+	/// Read all of the key-value pairs of the raw genesis of the chain-spec at `path`.
+	fn read_chain_spec(path: &Path) -> Vec<KeyPair> {
+		let file = fs::File::open(path).expect("State file could not be opened");
+		let spec: ChainSpec = serde_json::from_reader(std::io::BufReader::new(file))
+			.expect("State file is not a valid chain-spec");
+		let raw = spec
+			.genesis
+			.raw
+			.expect("State file must contain a raw genesis; use `build-spec --raw`");
+
+		if !raw.children_default.is_empty() {
+			warn!(
+				target: LOG_TARGET,
+				"ignoring {} child tries of the state file",
+				raw.children_default.len(),
+			);
+		}
+		raw.top.into_iter().collect()
+	}
+
+	/// Build `Self` from the snapshot or the raw genesis of the chain-spec at `path`.
+	fn scrape_file(&self, path: &Path) -> Vec<KeyPair> {
+		info!(target: LOG_TARGET, "reading keypairs from state file {:?}", path);
+		let pairs = match snapshot::load(path) {
+			Ok((header, pairs)) => {
 				info!(
 					target: LOG_TARGET,
-					"downloaded data for module {} (count: {} / prefix: {:?}).",
-					f,
-					module_kv.len(),
-					hashed_prefix,
+					"state file is a snapshot of {} @ {:?}",
+					header.chain,
+					header.at
 				);
-				filtered_kv.extend(module_kv);
+				pairs
 			}
-			filtered_kv
-		} else {
-			info!(target: LOG_TARGET, "downloading data for all modules.");
-			self.rpc_get_pairs(StorageKey(vec![]), at).await.into_iter().collect::<Vec<_>>()
+			Err(snapshot::Error::BadMagic) => Self::read_chain_spec(path),
+			Err(why) => panic!("State file is a corrupt snapshot: {}", why),
 		};
 
+		let mut keys_and_values =
+			pairs.into_iter().filter(|(k, _)| self.is_wanted(&k.0)).collect::<Vec<_>>();
+		info!(target: LOG_TARGET, "read {} keys from state file", keys_and_values.len());
+
 		// concat any custom key values.
 		keys_and_values.extend(self.inject.clone());
 		keys_and_values
 	}
 
+	/// Check the storage root of `ext` against the state root of the block.
+	///
+	/// On mismatch, this walks over all of the storage item prefixes of the remote node and reports
+	/// the ones that are not present in `local_prefixes`, then panics.
+	async fn check_state_root(
+		&self,
+		ext: &mut TestExternalities,
+		local_prefixes: &BTreeSet<Vec<u8>>,
+	) {
+		let at = self.final_at();
+		let expected = self.rpc_get_state_root(at).await;
+		let computed = match verify_state_root(ext, expected) {
+			Ok(computed) => {
+				info!(target: LOG_TARGET, "state root verified ({:?}).", computed);
+				return;
+			}
+			Err(computed) => computed,
+		};
+
+		error!(
+			target: LOG_TARGET,
+			"state root mismatch: computed {:?}, expected {:?}. Looking for missing prefixes.",
+			computed,
+			expected,
+		);
+
+		let mut missing = vec![];
+		let mut start_key: Option<StorageKey> = None;
+		while missing.len() < MAX_MISSING_PREFIX_SAMPLES {
+			let next =
+				Self::rpc_get_keys_paged(self.rpc_client(), StorageKey(vec![]), 1, start_key, at)
+					.await;
+			let key = match next.into_iter().next() {
+				Some(key) => key,
+				None => break,
+			};
+
+			let prefix = item_prefix(&key.0).to_vec();
+			if !local_prefixes.contains(&prefix) {
+				error!(target: LOG_TARGET, "missing prefix {:?}", prefix.hex_display());
+				missing.push(prefix.clone());
+			}
+
+			// skip all the other keys of this prefix.
+			let mut skip = prefix;
+			skip.extend_from_slice(&[u8::max_value(); 64]);
+			start_key = Some(StorageKey(skip));
+		}
+
+		panic!(
+			"State root mismatch at {:?}: computed {:?}, expected {:?}. Sample of missing \
+			prefixes: {:?}. If none are reported, some keys within the existing prefixes are \
+			missing or stale.",
+			at,
+			computed,
+			expected,
+			missing.iter().map(|p| format!("{:?}", p.hex_display())).collect::<Vec<_>>(),
+		);
+	}
+
 	async fn force_update(&self) -> Vec<KeyPair> {
 		let kp = self.scrape_remote().await;
-		self.save_cache(&kp);
+		self.save_cache(&kp).await;
 		kp
 	}
 
-	async fn pre_build(mut self) -> Vec<KeyPair> {
-		self.client = Some(
-			HttpClient::new(
-				self.uri.clone(),
-				HttpConfig { max_request_body_size: u32::max_value() },
-			)
-			.unwrap(),
-		);
+	async fn pre_build(&mut self) -> Vec<KeyPair> {
+		if let Some(path) = self.state_file.clone() {
+			return self.scrape_file(&path);
+		}
+
+		self.clients = (0..self.connections.max(1))
+			.map(|_| {
+				HttpClient::new(
+					self.uri.clone(),
+					HttpConfig { max_request_body_size: u32::max_value() },
+				)
+				.unwrap()
+			})
+			.collect();
+		let at_given = self.at.is_some();
 		self.at = match self.at {
 			Some(at) => Some(at),
 			None => Some(self.rpc_get_head().await),
@@ -372,10 +1135,14 @@ impl Builder {
 		match self.cache_config {
 			CacheMode::None => self.scrape_remote().await,
 			CacheMode::ForceUpdate => self.force_update().await,
-			CacheMode::UseElseCreate => match self.try_scrape_cached() {
-				Ok(kp) => kp,
+			CacheMode::UseElseCreate => match self.try_scrape_cached(at_given) {
+				Ok((header, kp)) => {
+					// a cache with a forced name might be at any block, unless specified.
+					self.at = Some(header.at);
+					kp
+				}
 				Err(why) => {
-					warn!(target: LOG_TARGET, "failed to load cache due to {:?}", why);
+					warn!(target: LOG_TARGET, "failed to load cache due to: {}", why);
 					self.force_update().await
 				}
 			},
@@ -416,9 +1183,46 @@ impl Builder {
 
 	/// Scrape only this module.
 	///
-	/// If used multiple times, all of the given modules will be used, else the entire chain.
+	/// If used multiple times, or alongside [`Self::storage_item`] or [`Self::raw_prefix`], all of
+	/// the given filters will be used, else the entire chain.
 	pub fn module(mut self, module: &str) -> Self {
-		self.module_filter.push(module.to_string());
+		self.filters.push(KeyFilter::Module(module.to_string()));
+		self
+	}
+
+	/// Scrape only this storage item of this module, e.g. `("Staking", "Nominators")`.
+	///
+	/// Can be combined with the other filters, similar to [`Self::module`].
+	pub fn storage_item(mut self, module: &str, item: &str) -> Self {
+		self.filters.push(KeyFilter::StorageItem(module.to_string(), item.to_string()));
+		self
+	}
+
+	/// Scrape only the keys that start with `prefix`.
+	///
+	/// Can be combined with the other filters, similar to [`Self::module`].
+	pub fn raw_prefix(mut self, prefix: &[u8]) -> Self {
+		self.filters.push(KeyFilter::RawPrefix(prefix.to_vec()));
+		self
+	}
+
+	/// Don't scrape any of the keys that match `filter`, e.g.
+	/// `KeyFilter::StorageItem("Staking".into(), "ErasStakers".into())`.
+	///
+	/// Applies to both the entire chain and to the other filters.
+	pub fn exclude(mut self, filter: KeyFilter) -> Self {
+		self.excludes.push(filter);
+		self
+	}
+
+	/// Build the state from the file at `path`, instead of a remote node.
+	///
+	/// The file must either be a snapshot (see [`save_snapshot`]), or a chain-spec with a raw
+	/// genesis, such as the output of `build-spec --raw` or `export-state`. The filters and
+	/// injections apply as usual, while [`Self::uri`], [`Self::at`] and the cache configuration are
+	/// ignored. Child tries are not supported.
+	pub fn from_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+		self.state_file = Some(path.as_ref().to_path_buf());
 		self
 	}
 
@@ -434,16 +1238,65 @@ impl Builder {
 		self
 	}
 
+	/// Configure the number of parallel connections used to scrape the remote node.
+	///
+	/// If not set, 4 connections are used.
+	pub fn connections(mut self, connections: usize) -> Self {
+		self.connections = connections;
+		self
+	}
+
+	/// Configure the compression of newly created cache files.
+	///
+	/// Loading a cache works regardless of this value, as the compression is stored in the file.
+	pub fn compression(mut self, compression: Compression) -> Self {
+		self.compression = compression;
+		self
+	}
+
+	/// Keep the keys of child tries, i.e. their roots, in the built externalities.
+	///
+	/// The storage root of the externalities then matches that of the chain, e.g. to compare it
+	/// after executing a block, but reading or writing a child trie fails, as its content is not
+	/// scraped. If not set, the keys of child tries are left out.
+	pub fn keep_child_roots(mut self, keep: bool) -> Self {
+		self.keep_child_roots = keep;
+		self
+	}
+
+	/// Configure the directory in which the cache files are read and written.
+	///
+	/// If not set, [`default_cache_dir`] will be used.
+	pub fn cache_dir<P: AsRef<Path>>(mut self, path: P) -> Self {
+		self.cache_dir = path.as_ref().to_path_buf();
+		self
+	}
+
 	/// Build the test externalities.
-	pub async fn build(self) -> TestExternalities {
+	///
+	/// If the entire state is scraped (i.e. no filters and no injections), the storage root
+	/// of the scraped state is checked against the state root of the block. This panics on
+	/// mismatch.
+	///
+	/// The content of child tries is not scraped, hence their keys are left out of the final
+	/// externalities (see [`ext_from_pairs`]), unless [`Self::keep_child_roots`] is set. The state
+	/// root check always includes them.
+	pub async fn build(mut self) -> TestExternalities {
 		let kv = self.pre_build().await;
-		let mut ext = TestExternalities::new_empty();
+		let children = child_trie_keys(&kv);
 
 		info!(target: LOG_TARGET, "injecting a total of {} keys", kv.len());
-		for (k, v) in kv {
-			let (k, v) = (k.0, v.0);
-			trace!(target: LOG_TARGET, "injecting {:?} -> {:?}", k.hex_display(), v.hex_display());
-			ext.insert(k, v);
+		let mut ext = if self.should_check_state_root() {
+			let local_prefixes =
+				kv.iter().map(|(k, _)| item_prefix(&k.0).to_vec()).collect::<BTreeSet<_>>();
+			let mut ext = raw_ext_from_pairs(kv);
+			self.check_state_root(&mut ext, &local_prefixes).await;
+			ext
+		} else {
+			raw_ext_from_pairs(kv)
+		};
+		if !self.keep_child_roots {
+			remove_child_tries(&mut ext, children);
 		}
 		ext
 	}
@@ -452,6 +1305,7 @@ impl Builder {
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use hex_literal::hex;
 	const TEST_URI: &'static str = "http://localhost:9933";
 
 	#[derive(Clone, Eq, PartialEq, Debug, Default)]
@@ -468,6 +1322,169 @@ mod tests {
 		Builder::new().uri(TEST_URI.into()).module("System").build().await.execute_with(|| {});
 	}
 
+	#[tokio::test]
+	#[ignore = "needs remove node"]
+	async fn can_build_storage_item() {
+		let _ = env_logger::Builder::from_default_env()
+			.format_module_path(false)
+			.format_level(true)
+			.try_init();
+
+		Builder::new()
+			.uri(TEST_URI.into())
+			.storage_item("System", "Number")
+			.storage_item("System", "BlockHash")
+			.build()
+			.await
+			.execute_with(|| {});
+	}
+
+	#[tokio::test]
+	async fn can_build_from_file() {
+		let path = std::env::temp_dir().join("remote-ext-chain-spec.json");
+		let staking = twox_128(b"Staking");
+		let system = twox_128(b"System");
+		let spec = serde_json::json!({
+			"name": "Test",
+			"genesis": { "raw": {
+				"top": {
+					format!("0x{}01", hex(&staking)): "0x01",
+					format!("0x{}02", hex(&staking)): "0x02",
+					format!("0x{}01", hex(&system)): "0x03",
+					"0x3a636f6465": "0x00",
+				},
+				"childrenDefault": {},
+			}},
+		});
+		fs::write(&path, spec.to_string()).unwrap();
+
+		let key = |prefix: &[u8], b: u8| [prefix, &[b]].concat();
+		Builder::new()
+			.from_file(&path)
+			.module("Staking")
+			.exclude(KeyFilter::RawPrefix(key(&staking, 2)))
+			.inject(&[(StorageKey(b"foo".to_vec()), StorageData(b"bar".to_vec()))])
+			.build()
+			.await
+			.execute_with(|| {
+				assert_eq!(sp_io::storage::get(&key(&staking, 1)), Some(vec![1]));
+				assert_eq!(sp_io::storage::get(&key(&staking, 2)), None);
+				assert_eq!(sp_io::storage::get(&key(&system, 1)), None);
+				assert_eq!(sp_io::storage::get(b":code"), None);
+				assert_eq!(sp_io::storage::get(b"foo"), Some(b"bar".to_vec()));
+			});
+
+		Builder::new().from_file(&path).build().await.execute_with(|| {
+			assert_eq!(sp_io::storage::get(&key(&system, 1)), Some(vec![3]));
+			assert_eq!(sp_io::storage::get(b":code"), Some(vec![0]));
+			// nothing but the state of the file is present.
+			assert_eq!(sp_io::storage::get(b":heappages"), None);
+		});
+
+		fs::remove_file(path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn can_save_and_build_from_snapshot() {
+		let path = std::env::temp_dir().join("remote-ext-saved-snapshot.bin");
+		let mut ext = ext_from_pairs(vec![
+			(StorageKey(b"foo".to_vec()), StorageData(b"bar".to_vec())),
+			(StorageKey(b"baz".to_vec()), StorageData(b"qux".to_vec())),
+		]);
+		ext.execute_with(|| {
+			sp_io::storage::set(b"foo", b"migrated");
+			sp_io::storage::clear(b"baz");
+		});
+
+		let header = save_snapshot(&mut ext, &path).unwrap();
+		assert_eq!(header.key_count, 1);
+		assert_eq!(header.state_root, Hash::from_slice(&ext.execute_with(sp_io::storage::root)));
+
+		let mut rebuilt = Builder::new().from_file(&path).build().await;
+		assert_eq!(ext_pairs(&mut rebuilt), ext_pairs(&mut ext));
+		rebuilt.execute_with(|| {
+			assert_eq!(sp_io::storage::get(b"foo"), Some(b"migrated".to_vec()));
+			assert_eq!(sp_io::storage::get(b"baz"), None);
+		});
+
+		fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn state_root_of_pairs_is_verified() {
+		// the root of the empty trie.
+		let empty: Hash =
+			hex!["03170a2e7597b7b7e3d84c05391d139a62b157e78786d8c082f29dcf4c111314"].into();
+		assert!(verify_state_root(&mut ext_from_pairs(vec![]), empty).is_ok());
+
+		// the root of a trie with the single leaf `foo -> bar`.
+		let foo: Hash =
+			hex!["cc561cd59bcef7911ca9d492b69fe05274a28f9c132bb70829b2cc452925e05b"].into();
+		let pairs = vec![(StorageKey(b"foo".to_vec()), StorageData(b"bar".to_vec()))];
+		assert!(verify_state_root(&mut ext_from_pairs(pairs.clone()), foo).is_ok());
+		assert_eq!(verify_state_root(&mut ext_from_pairs(pairs), empty), Err(foo));
+
+		// a seeded key is kept if it is part of the state.
+		let code = vec![(StorageKey(b":code".to_vec()), StorageData(vec![]))];
+		assert_eq!(ext_pairs(&mut ext_from_pairs(code.clone())), code);
+	}
+
+	#[test]
+	fn state_root_of_code_is_verified() {
+		// the root of a trie with the single leaf `:code -> 0x010203`.
+		let root: Hash =
+			hex!["e1dd5047c55882f1d3486dd88e5ba22d19aafcbe0f1d8558b56e1e574c068fbb"].into();
+		let code = vec![(StorageKey(b":code".to_vec()), StorageData(vec![1, 2, 3]))];
+		let mut ext = ext_from_pairs(code.clone());
+		assert_eq!(verify_state_root(&mut ext, root), Ok(root));
+		assert_eq!(ext_pairs(&mut ext), code);
+		ext.execute_with(|| assert_eq!(sp_io::storage::get(b":heappages"), None));
+	}
+
+	#[test]
+	fn child_tries_are_verified_then_left_out() {
+		// the root of a trie with the single leaf `:child_storage:default:foo -> [1; 32]`.
+		let root: Hash =
+			hex!["fd19d187fd4904c5fdcbebc623d999daa170880727138891c37eaf5aa9cdce96"].into();
+		let key = StorageKey(b":child_storage:default:foo".to_vec());
+		let child = vec![(key, StorageData(vec![1; 32]))];
+
+		let mut raw = raw_ext_from_pairs(child.clone());
+		assert_eq!(verify_state_root(&mut raw, root), Ok(root));
+		assert_eq!(child_trie_keys(&child), vec![b":child_storage:default:foo".to_vec()]);
+		remove_child_tries(&mut raw, child_trie_keys(&child));
+		assert!(ext_pairs(&mut raw).is_empty());
+
+		let mut ext = ext_from_pairs(child);
+		assert!(ext_pairs(&mut ext).is_empty());
+	}
+
+	fn hex(bytes: &[u8]) -> String {
+		bytes.iter().map(|b| format!("{:02x}", b)).collect()
+	}
+
+	#[test]
+	fn filters_work() {
+		let module = KeyFilter::Module("Staking".into());
+		let item = KeyFilter::StorageItem("Staking".into(), "Nominators".into());
+		let raw = KeyFilter::RawPrefix(vec![0x3a, 0x63]);
+
+		assert_eq!(module.prefix().len(), 16);
+		assert_eq!(item.prefix().len(), 32);
+		assert!(item.prefix().starts_with(&module.prefix()));
+		assert_eq!(raw.prefix(), vec![0x3a, 0x63]);
+
+		let builder = Builder::new()
+			.module("System")
+			.storage_item("Staking", "Nominators")
+			.raw_prefix(&[0x3a, 0x63])
+			.exclude(KeyFilter::StorageItem("System".into(), "Events".into()));
+		assert_eq!(
+			builder.filter_names(),
+			vec!["System", "Staking.Nominators", "0x3a63", "-System.Events"]
+		);
+	}
+
 	#[tokio::test]
 	#[ignore = "needs remove node"]
 	async fn can_create_cache() {
@@ -484,7 +1501,7 @@ mod tests {
 			.await
 			.execute_with(|| {});
 
-		let to_delete = std::fs::read_dir(Builder::cache_dir())
+		let to_delete = std::fs::read_dir(default_cache_dir())
 			.unwrap()
 			.into_iter()
 			.map(|d| d.unwrap())
@@ -0,0 +1,293 @@
+//! The on-disk format of the cache files, a.k.a. snapshots.
+//!
+//! A snapshot file is laid out as follows:
+//!
+//! 1. [`MAGIC`], to recognize the file.
+//! 2. The format version as little endian `u16`. Must be equal to [`VERSION`].
+//! 3. The `bincode` encoded [`SnapshotHeader`].
+//! 4. The `bincode` encoded key-value pairs, potentially compressed as denoted by
+//!    [`SnapshotHeader::compression`].
+//!
+//! The header contains the number of keys and the `blake2_256` checksum of the uncompressed
+//! payload, hence a truncated or otherwise corrupt file is detected upon loading.
+
+use crate::{Hash, KeyPair};
+use serde::{Deserialize, Serialize};
+use sp_core::hashing::blake2_256;
+use std::{
+	fmt::{Display, Formatter, Result as FmtResult},
+	fs, io,
+	io::{Read, Write},
+	path::Path,
+	time::SystemTime,
+};
+
+/// The first bytes of any snapshot file.
+pub const MAGIC: &[u8; 8] = b"REMOTEXT";
+
+/// The current version of the snapshot format.
+pub const VERSION: u16 = 1;
+
+/// The compression applied to the payload of a snapshot.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Compression {
+	/// The payload is stored as-is.
+	None,
+	/// The payload is compressed with gzip.
+	Gzip,
+}
+
+impl Default for Compression {
+	fn default() -> Self {
+		Self::None
+	}
+}
+
+/// The metadata stored at the beginning of each snapshot.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+	/// Name of the chain, as reported by `system_chain`.
+	pub chain: String,
+	/// The block hash at which the state was scraped.
+	pub at: Hash,
+	/// The state root of the block at which the state was scraped.
+	pub state_root: Hash,
+	/// The spec version of the runtime at the block at which the state was scraped.
+	pub spec_version: u32,
+	/// The key filters used, as named by [`crate::CacheName::Auto`]. Empty means the entire state.
+	pub filters: Vec<String>,
+	/// Creation time, in seconds since unix epoch.
+	pub created: u64,
+	/// The compression of the payload.
+	pub compression: Compression,
+	/// Number of keys in the payload.
+	pub key_count: u64,
+	/// `blake2_256` hash of the uncompressed payload.
+	pub checksum: [u8; 32],
+}
+
+impl SnapshotHeader {
+	/// Create a new header. The creation time is set to now, the key count and checksum are
+	/// populated upon [`save`].
+	pub fn new(
+		chain: String,
+		at: Hash,
+		state_root: Hash,
+		spec_version: u32,
+		filters: Vec<String>,
+		compression: Compression,
+	) -> Self {
+		let created = SystemTime::now()
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.map(|d| d.as_secs())
+			.unwrap_or_default();
+		Self {
+			chain,
+			at,
+			state_root,
+			spec_version,
+			filters,
+			created,
+			compression,
+			key_count: 0,
+			checksum: Default::default(),
+		}
+	}
+}
+
+/// Errors that can happen while reading or writing a snapshot.
+#[derive(Debug)]
+pub enum Error {
+	/// Underlying io error.
+	Io(io::Error),
+	/// The file does not start with [`MAGIC`]; it is not a snapshot, or a legacy cache file.
+	BadMagic,
+	/// The file is a snapshot, but of an unsupported format version.
+	UnsupportedVersion(u16),
+	/// The header or payload could not be decoded.
+	Decode(String),
+	/// The checksum of the payload does not match the one in the header.
+	ChecksumMismatch,
+	/// The number of keys in the payload does not match the one in the header.
+	KeyCountMismatch { expected: u64, got: u64 },
+	/// A field of the header does not match what was expected by the caller.
+	HeaderMismatch { field: &'static str, expected: String, got: String },
+}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		match self {
+			Self::Io(e) => write!(f, "io error: {}", e),
+			Self::BadMagic => write!(f, "not a snapshot file (bad magic)"),
+			Self::UnsupportedVersion(v) => {
+				write!(f, "unsupported snapshot version {} (expected {})", v, VERSION)
+			}
+			Self::Decode(e) => write!(f, "failed to decode snapshot: {}", e),
+			Self::ChecksumMismatch => {
+				write!(f, "payload checksum mismatch; the file is corrupt or truncated")
+			}
+			Self::KeyCountMismatch { expected, got } => {
+				write!(f, "header denotes {} keys, payload contains {}", expected, got)
+			}
+			Self::HeaderMismatch { field, expected, got } => {
+				write!(f, "snapshot {} mismatch: expected {}, got {}", field, expected, got)
+			}
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+impl From<bincode::Error> for Error {
+	fn from(e: bincode::Error) -> Self {
+		Self::Decode(e.to_string())
+	}
+}
+
+/// Read and check the magic and version, and decode the header from `reader`.
+fn read_header_from<R: Read>(mut reader: R) -> Result<SnapshotHeader, Error> {
+	let mut magic = [0u8; 8];
+	reader.read_exact(&mut magic).map_err(|_| Error::BadMagic)?;
+	if &magic != MAGIC {
+		return Err(Error::BadMagic);
+	}
+
+	let mut version = [0u8; 2];
+	reader.read_exact(&mut version)?;
+	let version = u16::from_le_bytes(version);
+	if version != VERSION {
+		return Err(Error::UnsupportedVersion(version));
+	}
+
+	Ok(bincode::deserialize_from(reader)?)
+}
+
+/// Read only the header of the snapshot at `path`, without reading the payload.
+pub fn read_header<P: AsRef<Path>>(path: P) -> Result<SnapshotHeader, Error> {
+	read_header_from(io::BufReader::new(fs::File::open(path)?))
+}
+
+/// Write `data` as a snapshot into `path`.
+///
+/// The key count and checksum of `header` are overwritten. The final header is returned.
+pub fn save<P: AsRef<Path>>(
+	path: P,
+	mut header: SnapshotHeader,
+	data: &[KeyPair],
+) -> Result<SnapshotHeader, Error> {
+	let payload = bincode::serialize(data)?;
+	header.key_count = data.len() as u64;
+	header.checksum = blake2_256(&payload);
+
+	let mut file = io::BufWriter::new(fs::File::create(path)?);
+	file.write_all(MAGIC)?;
+	file.write_all(&VERSION.to_le_bytes())?;
+	bincode::serialize_into(&mut file, &header)?;
+	match header.compression {
+		Compression::None => file.write_all(&payload)?,
+		Compression::Gzip => {
+			let mut encoder =
+				flate2::write::GzEncoder::new(&mut file, flate2::Compression::default());
+			encoder.write_all(&payload)?;
+			encoder.finish()?;
+		}
+	}
+	file.flush()?;
+
+	Ok(header)
+}
+
+/// Load the snapshot at `path`, checking the payload against the header.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<(SnapshotHeader, Vec<KeyPair>), Error> {
+	let mut reader = io::BufReader::new(fs::File::open(path)?);
+	let header = read_header_from(&mut reader)?;
+
+	let mut payload = vec![];
+	match header.compression {
+		Compression::None => reader.read_to_end(&mut payload)?,
+		Compression::Gzip => flate2::read::GzDecoder::new(reader).read_to_end(&mut payload)?,
+	};
+
+	if blake2_256(&payload) != header.checksum {
+		return Err(Error::ChecksumMismatch);
+	}
+
+	let data: Vec<KeyPair> = bincode::deserialize(&payload)?;
+	if data.len() as u64 != header.key_count {
+		return Err(Error::KeyCountMismatch { expected: header.key_count, got: data.len() as u64 });
+	}
+
+	Ok((header, data))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_core::storage::{StorageData, StorageKey};
+
+	fn test_data() -> Vec<KeyPair> {
+		(0..100u8).map(|i| (StorageKey(vec![i; 32]), StorageData(vec![i; 64]))).collect()
+	}
+
+	fn test_header(compression: Compression) -> SnapshotHeader {
+		SnapshotHeader::new(
+			"Kusama".into(),
+			Hash::repeat_byte(1),
+			Hash::repeat_byte(2),
+			2030,
+			vec!["Staking".into()],
+			compression,
+		)
+	}
+
+	fn test_path(name: &str) -> std::path::PathBuf {
+		std::env::temp_dir().join(format!("remote-ext-snapshot-{}.bin", name))
+	}
+
+	#[test]
+	fn save_load_works() {
+		for compression in [Compression::None, Compression::Gzip] {
+			let path = test_path(&format!("{:?}", compression));
+			let saved = save(&path, test_header(compression), &test_data()).unwrap();
+			assert_eq!(saved.key_count, 100);
+
+			assert_eq!(read_header(&path).unwrap(), saved);
+			let (header, data) = load(&path).unwrap();
+			assert_eq!(header, saved);
+			assert_eq!(data, test_data());
+
+			fs::remove_file(path).unwrap();
+		}
+	}
+
+	#[test]
+	fn detects_truncation() {
+		let path = test_path("truncated");
+		save(&path, test_header(Compression::None), &test_data()).unwrap();
+		let bytes = fs::read(&path).unwrap();
+		fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+
+		assert!(matches!(load(&path), Err(Error::ChecksumMismatch)));
+		fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn detects_legacy_and_unknown_versions() {
+		let path = test_path("legacy");
+		fs::write(&path, bincode::serialize(&test_data()).unwrap()).unwrap();
+		assert!(matches!(load(&path), Err(Error::BadMagic)));
+
+		let mut bytes = MAGIC.to_vec();
+		bytes.extend_from_slice(&(VERSION + 1).to_le_bytes());
+		fs::write(&path, bytes).unwrap();
+		assert!(matches!(load(&path), Err(Error::UnsupportedVersion(v)) if v == VERSION + 1));
+
+		fs::remove_file(path).unwrap();
+	}
+}
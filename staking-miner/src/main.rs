@@ -1,8 +1,101 @@
 mod mock_runtime;
 mod offchain_miner;
 mod polkadot_weight;
+mod report;
+mod submit;
 
-use sub_storage::Client;
+use mock_runtime::Balance;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use sub_storage::{Client, Hash};
+
+/// The election algorithm used to mine a solution.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Algorithm {
+	/// Sequential phragmen, followed by the given number of balancing iterations.
+	SeqPhragmen,
+	/// Phragmms, followed by the given number of balancing iterations.
+	Phragmms,
+}
+
+impl std::str::FromStr for Algorithm {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"seq-phragmen" => Ok(Self::SeqPhragmen),
+			"phragmms" => Ok(Self::Phragmms),
+			_ => Err("algorithm must be one of `seq-phragmen` or `phragmms`"),
+		}
+	}
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+	name = "staking-miner",
+	about = "mine and submit staking election solutions for a substrate chain"
+)]
+pub(crate) struct Opt {
+	/// The node to connect to.
+	#[structopt(long, default_value = "ws://localhost:9944")]
+	pub(crate) uri: String,
+
+	/// The http endpoint of the same node, used to scrape the staking state.
+	#[structopt(long, default_value = "http://localhost:9933")]
+	pub(crate) state_uri: String,
+
+	/// The block hash at which the solution is mined. If not provided, the latest finalized head
+	/// is used. Ignored in loop mode.
+	#[structopt(long)]
+	pub(crate) at: Option<Hash>,
+
+	/// Number of balancing iterations applied after the election algorithm.
+	#[structopt(long, default_value = "10")]
+	pub(crate) iterations: usize,
+
+	/// The election algorithm, one of `seq-phragmen` or `phragmms`.
+	#[structopt(long, default_value = "seq-phragmen")]
+	pub(crate) algorithm: Algorithm,
+
+	/// The file to which the encoded solution is written. If not provided, `era{index}_solution` is
+	/// used.
+	#[structopt(long)]
+	pub(crate) output: Option<PathBuf>,
+
+	/// Only mine and check the solution, never submit it.
+	#[structopt(long)]
+	pub(crate) dry_run: bool,
+
+	/// Run forever, and mine and submit a solution whenever the election window opens, without
+	/// asking for confirmation.
+	#[structopt(long = "loop")]
+	pub(crate) run_loop: bool,
+
+	/// The interval at which the election status is checked in loop mode, in seconds.
+	#[structopt(long, default_value = "12")]
+	pub(crate) poll_interval: u64,
+
+	/// The secret uri of the sr25519 account that signs the solution, e.g. a mnemonic, a hex seed
+	/// or `//Alice`.
+	#[structopt(long, conflicts_with = "keystore")]
+	pub(crate) seed: Option<String>,
+
+	/// A file that contains the secret uri of the signing account, e.g. a substrate keystore file.
+	#[structopt(long)]
+	pub(crate) keystore: Option<PathBuf>,
+
+	/// The number of blocks for which the signed solution is valid. Zero makes it immortal.
+	#[structopt(long, default_value = "32")]
+	pub(crate) mortality: u64,
+
+	/// The tip paid for the submission.
+	#[structopt(long, default_value = "0")]
+	pub(crate) tip: Balance,
+
+	/// Watch the submission until it is finalized, rather than until it is included in a block.
+	#[structopt(long)]
+	pub(crate) wait_finalization: bool,
+}
 
 #[async_std::main]
 async fn main() {
@@ -10,9 +103,15 @@ async fn main() {
 		.format_module_path(true)
 		.format_level(true)
 		.init();
+	let opt = Opt::from_args();
 
-	let client: Client = sub_storage::create_ws_client("ws://localhost:9944").await;
-	let now = sub_storage::get_head(&client).await;
+	let signer = match (&opt.seed, &opt.keystore) {
+		(Some(seed), _) => Some(submit::signer_from_seed(seed)),
+		(None, Some(path)) => Some(submit::signer_from_keystore(path)),
+		(None, None) if opt.dry_run => None,
+		(None, None) => panic!("Either `--seed` or `--keystore` is needed, unless `--dry-run`."),
+	};
 
-	offchain_miner::run(&client, now).await
+	let client: Client = sub_storage::create_ws_client(&opt.uri).await;
+	offchain_miner::run(&client, &opt, signer.as_ref()).await
 }
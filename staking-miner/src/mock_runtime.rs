@@ -17,6 +17,7 @@ use std::cell::RefCell;
 pub(crate) type AccountId = sp_core::crypto::AccountId32;
 pub(crate) type BlockNumber = u32;
 pub(crate) type Balance = u128;
+pub(crate) type Index = u32;
 pub(crate) type Header = sp_runtime::generic::Header<BlockNumber, sp_runtime::traits::BlakeTwo256>;
 
 pub(crate) type Session = pallet_session::Module<Runtime>;
@@ -66,7 +67,7 @@ impl frame_system::Config for Runtime {
 	type Hash = H256;
 	type Hashing = ::sp_runtime::traits::BlakeTwo256;
 	type Header = Header;
-	type Index = u32;
+	type Index = Index;
 	type Lookup = IdentityLookup<Self::AccountId>;
 	type MaximumBlockLength = MaximumBlockLength;
 	type MaximumBlockWeight = MaximumBlockWeight;
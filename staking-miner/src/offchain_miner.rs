@@ -1,68 +1,329 @@
-use crate::mock_runtime::{offchainify, Runtime, Staking, Timestamp};
+use crate::{
+	mock_runtime::{
+		AccountId, AvailableBlockRatio, BlockNumber, MaximumBlockLength, MinSolutionScoreBump,
+		Runtime, Staking, Timestamp,
+	},
+	report::Report,
+	submit::{self, Outcome, SignOptions},
+	Algorithm, Opt,
+};
 use codec::Encode;
-use frame_support::traits::OffchainWorker;
 use frame_support::{
-	storage::StorageValue, traits::UnfilteredDispatchable, weights::GetDispatchInfo,
+	storage::StorageValue,
+	traits::{Get, UnfilteredDispatchable},
+	weights::Weight,
 };
 use pallet_staking::{
-	offchain_election::prepare_submission, ElectionStatus, EraElectionStatus, OffchainAccuracy,
+	offchain_election::prepare_submission, CompactAssignments, ElectionScore, ElectionSize,
+	ElectionStatus, EraElectionStatus, EraIndex, Nominations, OffchainAccuracy, ValidatorIndex,
 	WeightInfo,
 };
-use separator::Separatable;
-use sp_npos_elections::ElectionResult;
+use sp_core::sr25519;
+use sp_npos_elections::{
+	assignment_ratio_to_staked_normalized, to_support_map, Assignment, ElectionResult,
+	ExtendedBalance, VoteWeight,
+};
+use sp_runtime::Perbill;
+use std::{cmp::Reverse, collections::BTreeSet, path::PathBuf, time::Duration};
 use sub_storage::{Client, Hash};
 
+const LOG_TARGET: &'static str = "staking-miner";
+
 /// Main function of this command.
-pub async fn run(_client: &Client, at: Hash) {
-	submit_at(at).await;
+pub async fn run(client: &Client, opt: &Opt, signer: Option<&sr25519::Pair>) {
+	if opt.run_loop {
+		run_loop(client, opt, signer).await
+	} else {
+		let at = match opt.at {
+			Some(at) => at,
+			None => sub_storage::get_head(client).await,
+		};
+		submit_at(client, at, opt, signer, true).await;
+	}
+}
+
+/// Wait for the election window to open, and mine and submit a solution for each era, forever.
+async fn run_loop(client: &Client, opt: &Opt, signer: Option<&sr25519::Pair>) {
+	let mut last_mined_era = None;
+	loop {
+		let at = sub_storage::get_head(client).await;
+		let status = sub_storage::read::<ElectionStatus<BlockNumber>>(
+			sub_storage::value_key(b"Staking", b"EraElectionStatus"),
+			client,
+			at,
+		)
+		.await
+		.unwrap_or(ElectionStatus::Closed);
+		let era = sub_storage::read::<EraIndex>(
+			sub_storage::value_key(b"Staking", b"CurrentEra"),
+			client,
+			at,
+		)
+		.await
+		.unwrap_or_default();
+
+		match status {
+			ElectionStatus::Open(opened_at) if last_mined_era != Some(era) => {
+				log::info!(
+					target: LOG_TARGET,
+					"election window of era {} opened at #{}, mining at {:?}",
+					era,
+					opened_at,
+					at
+				);
+				submit_at(client, at, opt, signer, false).await;
+				last_mined_era = Some(era);
+			}
+			_ => log::debug!(target: LOG_TARGET, "era {}: election status {:?}", era, status),
+		}
+
+		async_std::task::sleep(Duration::from_secs(opt.poll_interval)).await;
+	}
+}
+
+/// Run phragmms on the staking snapshot, mirroring how `do_phragmen` collects the voters.
+fn do_phragmms(iterations: usize) -> Option<ElectionResult<AccountId, OffchainAccuracy>> {
+	let weight_of = Staking::slashable_balance_of_fn();
+	let targets = Staking::snapshot_validators()?;
+	let nominators = Staking::snapshot_nominators()?;
+
+	let mut voters: Vec<(AccountId, VoteWeight, Vec<AccountId>)> =
+		targets.iter().map(|v| (v.clone(), weight_of(v), vec![v.clone()])).collect();
+	voters.extend(nominators.into_iter().filter_map(|n| {
+		let Nominations { submitted_in, mut targets, .. } = Staking::nominators(&n)?;
+		// filter out the nominations that were submitted before the last non-zero slash.
+		targets.retain(|stash| {
+			Staking::slashing_spans(&stash)
+				.map_or(true, |spans| submitted_in >= spans.last_nonzero_slash())
+		});
+		Some((n.clone(), weight_of(&n), targets))
+	}));
+
+	sp_npos_elections::phragmms::<AccountId, OffchainAccuracy>(
+		Staking::validator_count() as usize,
+		targets,
+		voters,
+		Some((iterations, 0)),
+	)
+	.ok()
+}
+
+/// The backing of each winner in the solution, sorted from the lowest to the highest.
+fn backing_of(
+	winners: &[(AccountId, ExtendedBalance)],
+	assignments: &[Assignment<AccountId, OffchainAccuracy>],
+) -> Vec<(AccountId, ExtendedBalance)> {
+	let winners = winners.iter().map(|(w, _)| w.clone()).collect::<Vec<_>>();
+	let staked = assignment_ratio_to_staked_normalized(
+		assignments.to_vec(),
+		Staking::slashable_balance_of_fn(),
+	)
+	.expect("Assignments of the election should normalize");
+	let supports = to_support_map(&winners, &staked).expect("Winners should have a support");
+	let mut backing =
+		supports.into_iter().map(|(who, support)| (who, support.total)).collect::<Vec<_>>();
+	backing.sort_by_key(|(_, total)| *total);
+	backing
+}
+
+/// A prepared solution, i.e. the arguments of `submit_election_solution` except for the era.
+type Solution = (Vec<ValidatorIndex>, CompactAssignments, ElectionScore, ElectionSize);
+
+/// The weight of submitting `solution`.
+fn solution_weight((winners, compact, _, size): &Solution) -> Weight {
+	<Runtime as pallet_staking::Config>::WeightInfo::submit_solution_better(
+		size.validators.into(),
+		size.nominators,
+		compact.len() as u32,
+		winners.len() as u32,
+	)
+}
+
+/// The encoded length of the `submit_election_solution` call of `solution`.
+fn solution_length(solution: &Solution, era: EraIndex) -> usize {
+	let (winners, compact, score, size) = solution.clone();
+	pallet_staking::Call::<Runtime>::submit_election_solution(winners, compact, score, era, size)
+		.encode()
+		.len()
+}
+
+/// The prefix of the encoded `frame_system::limits::BlockWeights`: `base_block`, `max_block`, and
+/// the `base_extrinsic` and `max_extrinsic` of normal extrinsics.
+type BlockWeightsPrefix = (Weight, Weight, Weight, Option<Weight>);
+
+/// The maximum weight of a normal extrinsic, without its base weight, as checked by `CheckWeight`.
+fn max_normal_weight((_, max_block, base_extrinsic, max_extrinsic): BlockWeightsPrefix) -> Weight {
+	max_extrinsic.unwrap_or(max_block).saturating_sub(base_extrinsic)
+}
+
+/// The maximum weight and length of a solution at block `at`, read from the constants of the
+/// chain.
+///
+/// The weight limit is `Staking::OffchainSolutionWeightLimit` if the chain exposes it, else the
+/// maximum weight of a normal extrinsic, as per `System::BlockWeights`. The length limit is the
+/// maximum length of a normal extrinsic, as per `System::BlockLength`. Either falls back to the
+/// mock runtime if the chain has no such constant.
+async fn solution_limits(client: &Client, at: Hash) -> (Weight, usize) {
+	let weight_limit = match sub_storage::get_const::<Weight>(
+		client,
+		"Staking",
+		"OffchainSolutionWeightLimit",
+		at,
+	)
+	.await
+	{
+		Some(limit) => limit,
+		None => sub_storage::get_const::<BlockWeightsPrefix>(client, "System", "BlockWeights", at)
+			.await
+			.map(max_normal_weight)
+			.unwrap_or_else(|| {
+				log::warn!(target: LOG_TARGET, "no weight limit on chain, using the mock runtime");
+				<Runtime as pallet_staking::Config>::OffchainSolutionWeightLimit::get()
+			}),
+	};
+
+	// the encoded `frame_system::limits::BlockLength` starts with the limit of normal extrinsics.
+	let length_limit = sub_storage::get_const::<u32>(client, "System", "BlockLength", at)
+		.await
+		.map(|length| length as usize)
+		.unwrap_or_else(|| {
+			log::warn!(target: LOG_TARGET, "no length limit on chain, using the mock runtime");
+			(AvailableBlockRatio::get() * MaximumBlockLength::get()) as usize
+		});
+
+	(weight_limit, length_limit)
 }
 
-pub async fn submit_at(at: Hash) {
-	remote_externalities::Builder::new()
+/// A solution that fits the limits of the chain.
+struct Trimmed {
+	/// The prepared solution.
+	solution: Solution,
+	/// The assignments that remain in the solution.
+	assignments: Vec<Assignment<AccountId, OffchainAccuracy>>,
+	/// The score of the solution before trimming.
+	untrimmed_score: ElectionScore,
+	/// The number of voters that were removed.
+	removed: usize,
+}
+
+/// Prepare a solution, and remove the voters with the lowest stake from it until it fits both
+/// `weight_limit` and `length_limit`.
+///
+/// The self votes of the winners are never removed. Each round removes a number of voters
+/// proportional to the excess, so only a handful of rounds are needed.
+fn trim_to_fit(
+	winners: &[(AccountId, ExtendedBalance)],
+	assignments: Vec<Assignment<AccountId, OffchainAccuracy>>,
+	era: EraIndex,
+	weight_limit: Weight,
+	length_limit: usize,
+) -> Trimmed {
+	let stake_of = Staking::slashable_balance_of_fn();
+	let winner_set = winners.iter().map(|(w, _)| w.clone()).collect::<BTreeSet<_>>();
+	let (fixed, mut trimmable): (Vec<_>, Vec<_>) =
+		assignments.into_iter().partition(|a| winner_set.contains(&a.who));
+	// highest stake first, so that trimming truncates the end.
+	trimmable.sort_by_key(|a| Reverse(stake_of(&a.who)));
+
+	let all = |trimmable: &[Assignment<AccountId, OffchainAccuracy>]| {
+		fixed.iter().chain(trimmable.iter()).cloned().collect::<Vec<_>>()
+	};
+	// the limits are checked here, so `prepare_submission` must not trim on its own.
+	let prepare = |assignments| {
+		prepare_submission::<Runtime>(assignments, winners.to_vec(), true, Weight::max_value())
+			.expect("Solution of the election should be valid")
+	};
+
+	let mut solution = prepare(all(&trimmable));
+	let untrimmed_score = solution.2;
+	let mut removed = 0;
+	loop {
+		let (weight, length) = (solution_weight(&solution), solution_length(&solution, era));
+		log::debug!(
+			target: LOG_TARGET,
+			"solution with {} voters: weight = {}, length = {}",
+			fixed.len() + trimmable.len(),
+			weight,
+			length
+		);
+		if weight <= weight_limit && length <= length_limit {
+			break
+		}
+		if trimmable.is_empty() {
+			log::warn!(target: LOG_TARGET, "solution does not fit even with no nominators left");
+			break
+		}
+
+		let ratio = (weight_limit as f64 / weight as f64).min(length_limit as f64 / length as f64);
+		let to_remove = ((trimmable.len() as f64 * (1.0 - ratio)).ceil() as usize)
+			.max(1)
+			.min(trimmable.len());
+		trimmable.truncate(trimmable.len() - to_remove);
+		removed += to_remove;
+		solution = prepare(all(&trimmable));
+	}
+
+	if removed > 0 {
+		log::info!(target: LOG_TARGET, "trimmed {} voters to fit the limits", removed);
+	}
+	Trimmed { solution, assignments: all(&trimmable), untrimmed_score, removed }
+}
+
+/// Mine a solution at block `at`, and submit it if the election window is open.
+///
+/// If `confirm` is true, the user is asked before submission. A `signer` is needed unless
+/// `opt.dry_run` is set.
+pub async fn submit_at(
+	client: &Client,
+	at: Hash,
+	opt: &Opt,
+	signer: Option<&sr25519::Pair>,
+	confirm: bool,
+) {
+	let min_score_bump =
+		sub_storage::get_const::<Perbill>(client, "Staking", "MinSolutionScoreBump", at)
+			.await
+			.unwrap_or_else(MinSolutionScoreBump::get);
+	let (weight_limit, length_limit) = solution_limits(client, at).await;
+
+	let (call, report, closed) = remote_externalities::Builder::new()
+		.uri(opt.state_uri.clone())
 		.module("Staking")
 		.at(at)
-		.build_async()
+		.build()
 		.await
 		.execute_with(|| {
 			let queued_score = Staking::queued_score();
-			log::info!("queued_score = {:?}", queued_score);
-			log::info!("now = {:?}", Timestamp::now());
-
-			// compute raw solution. Note that we use `OffchainAccuracy`.
-			let ElectionResult {
-				winners,
-				assignments,
-			} = Staking::do_phragmen::<OffchainAccuracy>(10).unwrap();
+			log::info!(target: LOG_TARGET, "queued_score = {:?}", queued_score);
+			log::info!(target: LOG_TARGET, "now = {:?}", Timestamp::now());
 
 			// Create the snapshot at any point.
 			let closed = Staking::era_election_status().is_closed();
 			if closed {
-				log::warn!("Election window is closed. This will not be submitted.");
+				log::warn!(
+					target: LOG_TARGET,
+					"Election window is closed. This will not be submitted."
+				);
 				Staking::create_stakers_snapshot();
 				<EraElectionStatus<Runtime>>::put(ElectionStatus::Open(999));
 			}
 
-			// process and prepare it for submission.
-			let (winners, compact, score, size) = prepare_submission::<Runtime>(
-				assignments,
-				winners,
-				true,
-				<Runtime as pallet_staking::Trait>::OffchainSolutionWeightLimit::get(),
-			)
-			.unwrap();
-
-			let weight = <Runtime as pallet_staking::Trait>::WeightInfo::submit_solution_better(
-				size.validators.into(),
-				size.nominators,
-				compact.len() as u32,
-				winners.len() as u32,
-			);
-			assert!(
-				weight <= <Runtime as pallet_staking::Trait>::OffchainSolutionWeightLimit::get()
-			);
+			// compute raw solution. Note that we use `OffchainAccuracy`.
+			let ElectionResult { winners, assignments } = match opt.algorithm {
+				Algorithm::SeqPhragmen => Staking::do_phragmen::<OffchainAccuracy>(opt.iterations),
+				Algorithm::Phragmms => do_phragmms(opt.iterations),
+			}
+			.expect("Election failed; is the staking snapshot available?");
 
 			let era = Staking::current_era().unwrap_or_default();
 
+			// process and prepare it for submission, trimming it if needed.
+			let Trimmed { solution, assignments, untrimmed_score, removed } =
+				trim_to_fit(&winners, assignments, era, weight_limit, length_limit);
+			let backing = backing_of(&winners, &assignments);
+			let weight = solution_weight(&solution);
+			let (winners, compact, score, size) = solution;
+
 			let inner_call = pallet_staking::Call::<Runtime>::submit_election_solution(
 				winners.clone(),
 				compact.clone(),
@@ -71,48 +332,105 @@ pub async fn submit_at(at: Hash) {
 				size,
 			);
 
-			let len = inner_call.encode().len();
-			let info = inner_call.get_dispatch_info();
+			let encoded_call = inner_call.encode();
+			let len = encoded_call.len();
 
 			log::info!(
-				"prepared a seq-phragmen solution with {} balancing iterations and score {:?} and weight = {:?} and len = {}",
-				10,
-				score.iter().map(|x| x.separated_string()).collect::<Vec<_>>(),
-				weight.separated_string(),
-				len,
+				target: LOG_TARGET,
+				"prepared a {:?} solution with {} balancing iterations",
+				opt.algorithm,
+				opt.iterations,
 			);
 
-			let pre_dispatch = frame_system::CheckWeight::<Runtime>::do_pre_dispatch(&info, len);
-			let validate = frame_system::CheckWeight::<Runtime>::do_validate(&info, len);
-			log::info!(
-				"Outcome of do_pre_dispatch: {:?} | validate = {:?}",
-				pre_dispatch,
-				validate
-			);
-			assert!(pre_dispatch.is_ok() && validate.is_ok());
+			let dispatch = inner_call
+				.dispatch_bypass_filter(crate::mock_runtime::Origin::signed(Default::default()))
+				.map(|_| ())
+				.map_err(|e| format!("{:?}", e.error));
+
+			let solution_file =
+				opt.output.clone().unwrap_or_else(|| PathBuf::from(format!("era{}_solution", era)));
+			std::fs::write(&solution_file, (winners, compact, score, era, size).encode()).unwrap();
+			log::info!(target: LOG_TARGET, "solution written to {:?}", solution_file);
 
-			let outcome = inner_call
-				.dispatch_bypass_filter(crate::mock_runtime::Origin::signed(Default::default()));
-			log::info!("Outcome of dispatch: {:?}", outcome);
+			let report = Report {
+				score,
+				queued_score,
+				min_score_bump,
+				weight,
+				weight_limit,
+				length: len,
+				length_limit,
+				untrimmed_score,
+				trimmed_voters: removed,
+				dispatch,
+				backing,
+			};
+			(encoded_call, report, closed)
+		});
 
-			let solution_file = format!("era{}_solution", era);
-			std::fs::write(
-				solution_file.clone(),
-				(winners, compact, score, era, size).encode(),
+	println!("{}", report);
+	let score = report.score;
+	if !report.is_submittable() {
+		log::error!(target: LOG_TARGET, "refusing to submit: {}", report.problems().join(", "));
+		return;
+	}
+	if closed || opt.dry_run {
+		return;
+	}
+	if confirm && !clt::confirm("Submit the solution?", false, "n", true) {
+		return;
+	}
+
+	let signer = signer.expect("A signer is needed to submit the solution");
+	let (pallet_index, call_index) =
+		submit::call_index(client, at, "Staking", "submit_election_solution").await;
+	assert_eq!(call[0], call_index, "Call index of the local runtime does not match the chain");
+	let call = std::iter::once(pallet_index).chain(call).collect::<Vec<_>>();
+
+	let options = SignOptions { mortality: opt.mortality, tip: opt.tip };
+	let extrinsic = submit::sign(client, signer, call, at, &options).await;
+	match submit::submit_and_watch(client, extrinsic, opt.wait_finalization).await {
+		Outcome::Included(block) | Outcome::Finalized(block) => {
+			// the extrinsic might be included while its dispatch failed, e.g. if a better solution
+			// was queued first, so check that the solution is indeed queued.
+			let queued = sub_storage::read::<ElectionScore>(
+				sub_storage::value_key(b"Staking", b"QueuedScore"),
+				client,
+				block,
 			)
-			.unwrap();
-
-			if !closed {
-				if clt::confirm("Submit the solution?", false, "n", true) {
-					let output = std::process::Command::new("node")
-						.args(&["js/build/index.js", &solution_file])
-						.output()
-						.unwrap();
-
-					log::info!("Exit code of js script = {}", output.status);
-					println!("STDOUT\n{}", String::from_utf8_lossy(&output.stdout));
-					println!("STDERR\n{}", String::from_utf8_lossy(&output.stderr));
-				}
+			.await;
+			if queued == Some(score) {
+				log::info!(target: LOG_TARGET, "solution queued in block {:?}", block);
+			} else {
+				log::error!(
+					target: LOG_TARGET,
+					"solution included in block {:?}, but not queued. queued score = {:?}",
+					block,
+					queued,
+				);
 			}
-		});
+		}
+		Outcome::Failed(why) => log::error!(target: LOG_TARGET, "submission failed: {}", why),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use frame_system::limits::{BlockLength, BlockWeights};
+
+	#[test]
+	fn limits_decode_from_chain_constants() {
+		let weights = BlockWeights::with_sensible_defaults(2_000, Perbill::from_percent(75));
+		let encoded = weights.encode();
+		let prefix = <BlockWeightsPrefix as codec::Decode>::decode(&mut &*encoded).unwrap();
+		let normal = weights.get(frame_support::weights::DispatchClass::Normal);
+		assert_eq!(
+			max_normal_weight(prefix),
+			normal.max_extrinsic.unwrap() - normal.base_extrinsic
+		);
+
+		let length = BlockLength::max_with_normal_ratio(1000, Perbill::from_percent(75));
+		assert_eq!(<u32 as codec::Decode>::decode(&mut &*length.encode()).unwrap(), 750);
+	}
 }
@@ -0,0 +1,171 @@
+//! The pre-submission report of a mined solution.
+
+use crate::mock_runtime::AccountId;
+use frame_support::weights::Weight;
+use separator::Separatable;
+use sp_npos_elections::{is_score_better, ElectionScore, ExtendedBalance};
+use sp_runtime::Perbill;
+use std::fmt;
+
+/// Everything that we know about a solution before submitting it.
+#[derive(Debug, Clone)]
+pub struct Report {
+	/// The score of our solution.
+	pub score: ElectionScore,
+	/// The score of the solution that is already queued on chain, if any.
+	pub queued_score: Option<ElectionScore>,
+	/// The minimum relative improvement over `queued_score` that the chain accepts.
+	pub min_score_bump: Perbill,
+	/// The weight of the submission.
+	pub weight: Weight,
+	/// The maximum weight of a solution on chain.
+	pub weight_limit: Weight,
+	/// The encoded length of the call.
+	pub length: usize,
+	/// The maximum length of a normal extrinsic on chain.
+	pub length_limit: usize,
+	/// The score of the solution before it was trimmed to fit the limits.
+	pub untrimmed_score: ElectionScore,
+	/// The number of voters that were removed to fit the limits.
+	pub trimmed_voters: usize,
+	/// The outcome of dispatching the call locally, which runs the feasibility check of the chain.
+	pub dispatch: Result<(), String>,
+	/// The backing of each winner, sorted from the lowest to the highest.
+	pub backing: Vec<(AccountId, ExtendedBalance)>,
+}
+
+impl Report {
+	/// Whether the solution strictly improves the queued one by at least `min_score_bump`.
+	pub fn improves_queued(&self) -> bool {
+		self.queued_score
+			.map_or(true, |queued| is_score_better(self.score, queued, self.min_score_bump))
+	}
+
+	/// The reasons for which this solution should not be submitted.
+	pub fn problems(&self) -> Vec<String> {
+		let mut problems = vec![];
+		if !self.improves_queued() {
+			problems.push(format!(
+				"score does not improve the queued score {:?} by {:?}",
+				self.queued_score.unwrap_or_default(),
+				self.min_score_bump,
+			));
+		}
+		if self.weight > self.weight_limit {
+			problems.push(format!("weight {} exceeds {}", self.weight, self.weight_limit));
+		}
+		if self.length > self.length_limit {
+			problems.push(format!("length {} exceeds {}", self.length, self.length_limit));
+		}
+		if let Err(why) = &self.dispatch {
+			problems.push(format!("dispatch failed: {}", why));
+		}
+		problems
+	}
+
+	/// Whether the solution can be submitted.
+	pub fn is_submittable(&self) -> bool {
+		self.problems().is_empty()
+	}
+}
+
+fn display_score(score: &ElectionScore) -> String {
+	format!("{:?}", score.iter().map(|x| x.separated_string()).collect::<Vec<_>>())
+}
+
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let check = |ok: bool| if ok { "ok" } else { "FAILED" };
+		let percent = |a: u128, b: u128| a as f64 * 100.0 / b.max(1) as f64;
+
+		writeln!(f, "score:        {}", display_score(&self.score))?;
+		match self.queued_score {
+			Some(queued) => writeln!(
+				f,
+				"queued score: {} (min bump {:?}) [{}]",
+				display_score(&queued),
+				self.min_score_bump,
+				check(self.improves_queued()),
+			)?,
+			None => writeln!(f, "queued score: none [ok]")?,
+		}
+		writeln!(
+			f,
+			"weight:       {} / {} ({:.2}%) [{}]",
+			self.weight.separated_string(),
+			self.weight_limit.separated_string(),
+			percent(self.weight as u128, self.weight_limit as u128),
+			check(self.weight <= self.weight_limit),
+		)?;
+		writeln!(
+			f,
+			"length:       {} / {} ({:.2}%) [{}]",
+			self.length.separated_string(),
+			self.length_limit.separated_string(),
+			percent(self.length as u128, self.length_limit as u128),
+			check(self.length <= self.length_limit),
+		)?;
+		if self.trimmed_voters > 0 {
+			let lost = |i: usize| self.untrimmed_score[i].saturating_sub(self.score[i]);
+			writeln!(
+				f,
+				"trimmed:      {} voters, lost {} ({:.2}%) of the minimum support and {} ({:.2}%) \
+				 of the total support",
+				self.trimmed_voters,
+				lost(0).separated_string(),
+				percent(lost(0), self.untrimmed_score[0]),
+				lost(1).separated_string(),
+				percent(lost(1), self.untrimmed_score[1]),
+			)?;
+		}
+		writeln!(f, "dispatch:     {:?}", self.dispatch)?;
+		writeln!(f, "backing of {} winners:", self.backing.len())?;
+		for (who, backing) in self.backing.iter() {
+			writeln!(f, "  {} {: >32}", who, backing.separated_string())?;
+		}
+		match self.problems().as_slice() {
+			[] => write!(f, "verdict: submittable"),
+			problems => write!(f, "verdict: not submittable: {}", problems.join(", ")),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn report(score: ElectionScore, queued_score: Option<ElectionScore>) -> Report {
+		Report {
+			score,
+			queued_score,
+			min_score_bump: Perbill::from_percent(10),
+			weight: 10,
+			weight_limit: 20,
+			length: 10,
+			length_limit: 20,
+			untrimmed_score: score,
+			trimmed_voters: 0,
+			dispatch: Ok(()),
+			backing: vec![],
+		}
+	}
+
+	#[test]
+	fn improvement_is_required() {
+		assert!(report([100, 100, 100], None).is_submittable());
+		// 5% better is not enough with a 10% bump.
+		assert!(!report([105, 100, 100], Some([100, 100, 100])).is_submittable());
+		assert!(report([111, 100, 100], Some([100, 100, 100])).is_submittable());
+		// equal scores never improve.
+		assert!(!report([100, 100, 100], Some([100, 100, 100])).improves_queued());
+	}
+
+	#[test]
+	fn limits_are_checked() {
+		let mut r = report([100, 100, 100], None);
+		r.weight = 21;
+		r.length = 21;
+		r.dispatch = Err("PhragmenBogusScore".into());
+		assert_eq!(r.problems().len(), 3);
+	}
+}
@@ -0,0 +1,221 @@
+//! Build, sign and submit extrinsics to a polkadot-like chain over RPC.
+//!
+//! The signed extensions of polkadot and kusama are assumed, namely `CheckSpecVersion`,
+//! `CheckTxVersion`, `CheckGenesis`, `CheckMortality`, `CheckNonce`, `CheckWeight` and
+//! `ChargeTransactionPayment`. Of these, only the era, nonce and tip are part of the extrinsic.
+//! The spec version, transaction version, genesis hash and the hash of the era's birth block are
+//! only part of the signed payload.
+
+use crate::mock_runtime::{AccountId, Balance, BlockNumber, Header, Index};
+use codec::{Compact, Decode, Encode};
+use frame_metadata::{DecodeDifferent, RuntimeMetadata, RuntimeMetadataPrefixed};
+use jsonrpsee_types::jsonrpc::{to_value as to_json_value, Params};
+use sp_core::{crypto::Pair as _, hashing::blake2_256, sr25519, Bytes};
+use sp_runtime::{generic::Era, traits::IdentifyAccount, MultiAddress, MultiSignature, MultiSigner};
+use sp_transaction_pool::TransactionStatus;
+use std::path::Path;
+use sub_storage::{Client, Hash};
+
+const LOG_TARGET: &'static str = "staking-miner";
+
+/// The version byte of a signed extrinsic, in format version 4.
+const SIGNED_EXTRINSIC_V4: u8 = 0b1000_0000 | 4;
+
+/// Create a signer from a secret uri, i.e. a mnemonic, a hex seed or a dev account such as
+/// `//Alice`, optionally followed by derivation junctions.
+pub fn signer_from_seed(seed: &str) -> sr25519::Pair {
+	sr25519::Pair::from_string(seed.trim(), None)
+		.unwrap_or_else(|why| panic!("Invalid secret uri: {:?}", why))
+}
+
+/// Create a signer from a keystore file.
+///
+/// Both the json files of a substrate keystore, which contain a single json string, and plain
+/// text files that contain the secret uri are supported.
+pub fn signer_from_keystore<P: AsRef<Path>>(path: P) -> sr25519::Pair {
+	let content = std::fs::read_to_string(path.as_ref())
+		.unwrap_or_else(|why| panic!("Failed to read keystore {:?}: {}", path.as_ref(), why));
+	let content = content.trim();
+	let seed = if content.starts_with('"') {
+		serde_json::from_str::<String>(content).expect("Keystore is not a valid json string")
+	} else {
+		content.to_string()
+	};
+	signer_from_seed(&seed)
+}
+
+/// The account id of a signer.
+pub fn account_of(signer: &sr25519::Pair) -> AccountId {
+	MultiSigner::from(signer.public()).into_account()
+}
+
+/// Get the next nonce of `who`, including the transactions in the pool of the node.
+pub async fn get_nonce(client: &Client, who: &AccountId) -> Index {
+	let who = to_json_value(who).expect("AccountId serialization infallible");
+	client
+		.request("system_accountNextIndex", Params::Array(vec![who]))
+		.await
+		.expect("get account next index request failed")
+}
+
+/// Find the index of the pallet `pallet` and of its call `call` in the metadata of the chain.
+pub async fn call_index(client: &Client, at: Hash, pallet: &str, call: &str) -> (u8, u8) {
+	let bytes = sub_storage::get_metadata(client, at).await;
+	let metadata = <RuntimeMetadataPrefixed as Decode>::decode(&mut &*bytes)
+		.expect("Runtime metadata should decode");
+	let modules = match metadata.1 {
+		RuntimeMetadata::V12(inner) => sub_storage::unwrap_decoded(inner.modules),
+		_ => panic!("Unsupported metadata version. Please make an issue."),
+	};
+	let module = modules
+		.into_iter()
+		.find(|m| matches!(&m.name, DecodeDifferent::Decoded(name) if name == pallet))
+		.unwrap_or_else(|| panic!("Pallet {} not found in the metadata", pallet));
+	let calls = module
+		.calls
+		.map(sub_storage::unwrap_decoded)
+		.unwrap_or_else(|| panic!("Pallet {} has no calls", pallet));
+	let index = calls
+		.iter()
+		.position(|c| matches!(&c.name, DecodeDifferent::Decoded(name) if name == call))
+		.unwrap_or_else(|| panic!("Call {}::{} not found in the metadata", pallet, call));
+	(module.index, index as u8)
+}
+
+/// The parameters of a signed extrinsic.
+pub struct SignOptions {
+	/// The number of blocks for which the extrinsic is valid. If zero, the extrinsic is immortal.
+	pub mortality: u64,
+	/// The tip paid to the block author.
+	pub tip: Balance,
+}
+
+/// Build and sign an extrinsic with the given `call` (which must already contain the pallet
+/// index), valid on top of the finalized block `at`.
+pub async fn sign(
+	client: &Client,
+	signer: &sr25519::Pair,
+	call: Vec<u8>,
+	at: Hash,
+	options: &SignOptions,
+) -> Vec<u8> {
+	let who = account_of(signer);
+	let nonce = get_nonce(client, &who).await;
+	let version = sub_storage::get_runtime_version(client, at).await;
+	let genesis = sub_storage::get_block_hash(client, 0).await.expect("Genesis must exist");
+
+	let (era, checkpoint) = if options.mortality == 0 {
+		(Era::Immortal, genesis)
+	} else {
+		let header = sub_storage::get_header::<Header>(client, at)
+			.await
+			.expect("Header of the finalized head must exist");
+		let current = header.number as u64;
+		let era = Era::mortal(options.mortality, current);
+		let birth = era.birth(current) as BlockNumber;
+		(era, sub_storage::get_block_hash(client, birth).await.expect("Birth block must exist"))
+	};
+	log::info!(
+		target: LOG_TARGET,
+		"signing as {} with nonce {}, era {:?}, tip {} (spec_version {}, transaction_version {})",
+		who,
+		nonce,
+		era,
+		options.tip,
+		version.spec_version,
+		version.transaction_version,
+	);
+
+	let extra = (era, Compact(nonce), Compact(options.tip));
+	let additional = (version.spec_version, version.transaction_version, genesis, checkpoint);
+	let signature = (&call, &extra, &additional).using_encoded(|payload| {
+		// long payloads are hashed first, as in `sp_runtime::generic::SignedPayload`.
+		if payload.len() > 256 {
+			signer.sign(&blake2_256(payload))
+		} else {
+			signer.sign(payload)
+		}
+	});
+
+	let mut extrinsic = vec![SIGNED_EXTRINSIC_V4];
+	MultiAddress::<AccountId, ()>::Id(who).encode_to(&mut extrinsic);
+	MultiSignature::Sr25519(signature).encode_to(&mut extrinsic);
+	extra.encode_to(&mut extrinsic);
+	extrinsic.extend(call);
+	// the extrinsic is length prefixed, like a `Vec<u8>`.
+	extrinsic.encode()
+}
+
+/// The final outcome of a submitted extrinsic.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Outcome {
+	/// The extrinsic was included in the given block.
+	Included(Hash),
+	/// The extrinsic was finalized in the given block.
+	Finalized(Hash),
+	/// The extrinsic was dropped, invalidated or usurped by the pool.
+	Failed(String),
+}
+
+/// Submit `extrinsic` and watch it until it is included in a block (if `wait_finalization` is
+/// false), finalized or dropped.
+pub async fn submit_and_watch(
+	client: &Client,
+	extrinsic: Vec<u8>,
+	wait_finalization: bool,
+) -> Outcome {
+	let extrinsic = to_json_value(Bytes(extrinsic)).expect("Extrinsic serialization infallible");
+	let mut subscription = client
+		.subscribe::<TransactionStatus<Hash, Hash>>(
+			"author_submitAndWatchExtrinsic",
+			Params::Array(vec![extrinsic]),
+			"author_unwatchExtrinsic",
+		)
+		.await
+		.expect("submit and watch extrinsic request failed");
+
+	while let Some(status) = subscription.next().await {
+		log::info!(target: LOG_TARGET, "extrinsic status: {:?}", status);
+		match status {
+			TransactionStatus::Future
+			| TransactionStatus::Ready
+			| TransactionStatus::Broadcast(_)
+			| TransactionStatus::Retracted(_) => continue,
+			TransactionStatus::InBlock(block) if !wait_finalization => {
+				return Outcome::Included(block)
+			}
+			TransactionStatus::InBlock(_) => continue,
+			TransactionStatus::Finalized(block) => return Outcome::Finalized(block),
+			TransactionStatus::FinalityTimeout(block) => {
+				return Outcome::Failed(format!("finality timeout in block {:?}", block))
+			}
+			TransactionStatus::Usurped(by) => {
+				return Outcome::Failed(format!("usurped by {:?}", by))
+			}
+			TransactionStatus::Dropped => return Outcome::Failed("dropped".to_string()),
+			TransactionStatus::Invalid => return Outcome::Failed("invalid".to_string()),
+		}
+	}
+
+	Outcome::Failed("subscription closed by the node".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn signer_from_keystore_works() {
+		let dir = std::env::temp_dir().join("staking-miner-keystore-test");
+		std::fs::create_dir_all(&dir).unwrap();
+		let alice = signer_from_seed("//Alice");
+
+		let plain = dir.join("plain");
+		std::fs::write(&plain, "//Alice\n").unwrap();
+		assert_eq!(signer_from_keystore(&plain).public(), alice.public());
+
+		let json = dir.join("json");
+		std::fs::write(&json, "\"//Alice\"").unwrap();
+		assert_eq!(signer_from_keystore(&json).public(), alice.public());
+	}
+}
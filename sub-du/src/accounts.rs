@@ -0,0 +1,207 @@
+//! Attribution of storage to the accounts that are embedded in the keys of storage maps.
+//!
+//! A map key only contains the account id in clear if it is hashed with a concatenating hasher
+//! (`Blake2_128Concat`, `Twox64Concat` or `Identity`). For double maps, an account in the second
+//! key can only be found if the first key has a known, fixed size.
+
+use crate::output::Size;
+use frame_metadata::{DecodeDifferent, DecodeDifferentStr, StorageEntryType, StorageHasher};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The account id type of polkadot-like chains.
+pub type AccountId = sp_core::crypto::AccountId32;
+
+/// The length of the prefix of each key, namely the hashed pallet and item names.
+pub const PREFIX_LEN: usize = 32;
+
+/// The length of an encoded [`AccountId`].
+pub const ACCOUNT_LEN: usize = 32;
+
+pub fn type_name(ty: &DecodeDifferentStr) -> &str {
+	match ty {
+		DecodeDifferent::Encode(name) => name,
+		DecodeDifferent::Decoded(name) => name.as_str(),
+	}
+}
+
+pub fn is_account(ty: &str) -> bool {
+	ty.ends_with("AccountId")
+}
+
+/// The encoded size of the types that commonly precede an account in double maps.
+pub fn fixed_size(ty: &str) -> Option<usize> {
+	match ty {
+		"u8" => Some(1),
+		"u16" => Some(2),
+		"u32" | "EraIndex" | "SessionIndex" | "T::BlockNumber" | "BlockNumber" => Some(4),
+		"u64" => Some(8),
+		"u128" => Some(16),
+		ty if is_account(ty) => Some(ACCOUNT_LEN),
+		_ => None,
+	}
+}
+
+/// The length of the hash that `hasher` puts before the key, if the key itself follows it.
+pub fn concat_offset(hasher: &StorageHasher) -> Option<usize> {
+	match hasher {
+		StorageHasher::Blake2_128Concat => Some(16),
+		StorageHasher::Twox64Concat => Some(8),
+		StorageHasher::Identity => Some(0),
+		StorageHasher::Blake2_128
+		| StorageHasher::Blake2_256
+		| StorageHasher::Twox128
+		| StorageHasher::Twox256 => None,
+	}
+}
+
+/// The offset of the account id in the keys of a storage item of type `ty`, after its prefix.
+///
+/// Returns `None` if the keys contain no account, or if it cannot be recovered.
+pub fn account_offset(ty: &StorageEntryType) -> Option<usize> {
+	match ty {
+		StorageEntryType::Plain(_) => None,
+		StorageEntryType::Map { hasher, key, .. } if is_account(type_name(key)) => {
+			concat_offset(hasher)
+		}
+		StorageEntryType::Map { .. } => None,
+		StorageEntryType::DoubleMap { hasher, key1, key2_hasher, key2, .. } => {
+			if is_account(type_name(key1)) {
+				concat_offset(hasher)
+			} else if is_account(type_name(key2)) {
+				Some(
+					concat_offset(hasher)?
+						+ fixed_size(type_name(key1))?
+						+ concat_offset(key2_hasher)?,
+				)
+			} else {
+				None
+			}
+		}
+	}
+}
+
+/// The storage used by a single account.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Footprint {
+	/// The number of bytes of the keys and values of all entries.
+	pub bytes: usize,
+	/// The number of entries.
+	pub entries: usize,
+	/// The bytes used in each storage item, by `Pallet::Item` name.
+	pub items: BTreeMap<String, usize>,
+}
+
+impl std::fmt::Display for Footprint {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let mut items = self.items.iter().collect::<Vec<_>>();
+		items.sort_by_key(|(_, bytes)| std::cmp::Reverse(**bytes));
+		let items = items
+			.into_iter()
+			.map(|(name, bytes)| format!("{} ({}B)", name, bytes))
+			.collect::<Vec<_>>();
+		write!(f, "{} in {} entries: {}", Size(self.bytes), self.entries, items.join(", "))
+	}
+}
+
+/// The storage used by an account.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AccountFootprint {
+	pub account: AccountId,
+	#[serde(flatten)]
+	pub footprint: Footprint,
+}
+
+impl std::fmt::Display for AccountFootprint {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} {}", self.account, self.footprint)
+	}
+}
+
+/// The storage used by all accounts that were seen so far.
+#[derive(Debug, Clone, Default)]
+pub struct Footprints(BTreeMap<AccountId, Footprint>);
+
+impl Footprints {
+	/// Attribute the entry with full `key` and `value` of `item` to the account at `offset`.
+	///
+	/// Returns false if the key is too short to contain an account at `offset`.
+	pub fn record(&mut self, item: &str, offset: usize, key: &[u8], value: &[u8]) -> bool {
+		let start = PREFIX_LEN + offset;
+		let mut raw = [0u8; ACCOUNT_LEN];
+		match key.get(start..start + ACCOUNT_LEN) {
+			Some(account) => raw.copy_from_slice(account),
+			None => return false,
+		}
+
+		let bytes = key.len() + value.len();
+		let footprint = self.0.entry(AccountId::from(raw)).or_default();
+		footprint.bytes += bytes;
+		footprint.entries += 1;
+		*footprint.items.entry(item.to_string()).or_default() += bytes;
+		true
+	}
+
+	/// The `n` accounts that use the most bytes, from the largest.
+	pub fn top(&self, n: usize) -> Vec<AccountFootprint> {
+		let mut all = self.0.iter().collect::<Vec<_>>();
+		all.sort_by_key(|(_, footprint)| std::cmp::Reverse(footprint.bytes));
+		all.into_iter()
+			.take(n)
+			.map(|(account, footprint)| AccountFootprint {
+				account: account.clone(),
+				footprint: footprint.clone(),
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{double_map, map, ty};
+
+	#[test]
+	fn account_offset_works() {
+		use StorageHasher::*;
+		assert_eq!(account_offset(&StorageEntryType::Plain(ty("T::AccountId"))), None);
+		assert_eq!(account_offset(&map(Blake2_128Concat, "T::AccountId")), Some(16));
+		assert_eq!(account_offset(&map(Twox64Concat, "T::AccountId")), Some(8));
+		assert_eq!(account_offset(&map(Twox64Concat, "EraIndex")), None);
+		// the account cannot be recovered from a plain hash.
+		assert_eq!(account_offset(&map(Blake2_256, "T::AccountId")), None);
+
+		// `Staking::ErasStakers`.
+		let stakers = double_map(Twox64Concat, "EraIndex", Twox64Concat, "T::AccountId");
+		assert_eq!(account_offset(&stakers), Some(8 + 4 + 8));
+		let unknown = double_map(Twox64Concat, "Vec<u8>", Twox64Concat, "T::AccountId");
+		assert_eq!(account_offset(&unknown), None);
+		let first = double_map(Blake2_128Concat, "T::AccountId", Twox64Concat, "u32");
+		assert_eq!(account_offset(&first), Some(16));
+	}
+
+	#[test]
+	fn footprints_are_recorded() {
+		let key = |hash: u8, account: u8| {
+			let mut key = vec![0u8; PREFIX_LEN];
+			key.extend(vec![hash; 8]);
+			key.extend(vec![account; ACCOUNT_LEN]);
+			key
+		};
+
+		let mut footprints = Footprints::default();
+		assert!(footprints.record("System::Account", 8, &key(0, 1), &[0; 10]));
+		assert!(footprints.record("Staking::Ledger", 8, &key(1, 1), &[0; 20]));
+		assert!(footprints.record("System::Account", 8, &key(2, 2), &[0; 10]));
+		assert!(!footprints.record("System::Account", 16, &key(2, 2), &[0; 10]));
+		assert_eq!(footprints.top(10).len(), 2);
+
+		let top = footprints.top(1);
+		assert_eq!(top.len(), 1);
+		assert_eq!(top[0].account, AccountId::from([1u8; ACCOUNT_LEN]));
+		assert_eq!(top[0].footprint.entries, 2);
+		assert_eq!(top[0].footprint.bytes, 2 * (PREFIX_LEN + 8 + ACCOUNT_LEN) + 30);
+		let ledger = PREFIX_LEN + 8 + ACCOUNT_LEN + 20;
+		assert_eq!(top[0].footprint.items["Staking::Ledger"], ledger);
+	}
+}
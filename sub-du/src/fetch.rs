@@ -0,0 +1,290 @@
+//! Fetching the storage of a chain, with a fallback to safe RPC calls.
+//!
+//! `state_getPairs`, and `state_getStorageSize` on a prefix, are unsafe RPC calls that public
+//! nodes deny. Once the node denies one of them, the keys under each prefix are instead enumerated
+//! with `state_getKeysPaged`, and the size or value of each key is queried on its own. This is
+//! much slower, but works against any node. Any other error of these calls is fatal.
+
+use crate::LOG_TARGET;
+use codec::{Decode, Encode};
+use jsonrpsee_types::jsonrpc::{Error as JsonRpcError, ErrorCode};
+use sp_core::{hashing::blake2_256, hexdisplay::HexDisplay};
+use std::collections::BTreeMap;
+use sub_storage::{Client, Hash, RpcError, StorageKey};
+
+/// The number of keys requested per `state_getKeysPaged` call.
+const PAGE_SIZE: u32 = 1000;
+
+/// The prefix of the keys of the default child tries in the main trie.
+pub const CHILD_STORAGE_PREFIX: &[u8] = b":child_storage:default:";
+
+/// A default child trie.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ChildTrie {
+	/// The key of the trie in the main trie, without [`CHILD_STORAGE_PREFIX`].
+	pub name: Vec<u8>,
+	/// The number of bytes of all values.
+	pub bytes: usize,
+	/// The number of keys.
+	pub keys: usize,
+}
+
+impl ChildTrie {
+	/// The name of the trie as a storage item: its name if it is ascii, else its name in hex.
+	pub fn item_name(&self) -> String {
+		if !self.name.is_empty() && self.name.iter().all(|b| b.is_ascii_graphic()) {
+			format!(":child_storage:{}", String::from_utf8_lossy(&self.name))
+		} else {
+			format!(":child_storage:0x{}", HexDisplay::from(&self.name.as_slice()))
+		}
+	}
+}
+
+/// The name of the child trie of the crowdloan with the given trie index, as `Crowdloan` derives
+/// it.
+pub fn crowdloan_trie(index: u32) -> Vec<u8> {
+	let mut name = b"crowdloan".to_vec();
+	name.extend_from_slice(&index.encode());
+	blake2_256(&name).to_vec()
+}
+
+/// The name of the child trie of a contract, i.e. the `trie_id` that leads its
+/// `Contracts::ContractInfoOf` value.
+///
+/// Older runtimes store an enum of `Alive` contracts, which own a trie, and `Tombstone`s, which
+/// do not. Newer ones store the info of alive contracts only. These are told apart by the first
+/// byte, as the length of a `trie_id` never encodes to 0 or 1.
+pub fn contract_trie(info: &[u8]) -> Option<Vec<u8>> {
+	match info.first()? {
+		0 => Vec::<u8>::decode(&mut &info[1..]).ok(),
+		1 => None,
+		_ => Vec::<u8>::decode(&mut &info[..]).ok(),
+	}
+}
+
+/// The message of substrate's `UnsafeRpcError`, which a node returns for unsafe calls unless it
+/// runs with `--rpc-methods unsafe`.
+const UNSAFE_RPC_MESSAGE: &str = "RPC call is unsafe to be called externally";
+
+/// Whether `why` is substrate's `UnsafeRpcError`.
+fn is_unsafe_denial(why: &RpcError) -> bool {
+	match why {
+		RpcError::Request(JsonRpcError { code: ErrorCode::MethodNotFound, message, .. }) => {
+			message == UNSAFE_RPC_MESSAGE
+		}
+		_ => false,
+	}
+}
+
+/// Fetches the storage of a chain, and remembers whether the node denies unsafe RPC calls.
+pub struct Fetcher<'a> {
+	pub client: &'a Client,
+	unsafe_denied: bool,
+}
+
+impl<'a> Fetcher<'a> {
+	pub fn new(client: &'a Client) -> Self {
+		Self { client, unsafe_denied: false }
+	}
+
+	/// Fall back to safe RPC calls if `why` is the denial of the unsafe `call`, else panic.
+	fn deny(&mut self, call: &str, why: RpcError) {
+		if !is_unsafe_denial(&why) {
+			panic!("{} failed: {:?}", call, why);
+		}
+		log::warn!(
+			target: LOG_TARGET,
+			"{} denied ({:?}), falling back to safe RPC calls. This is much slower.",
+			call,
+			why,
+		);
+		self.unsafe_denied = true;
+	}
+
+	/// All keys under `prefix`, enumerated one page at a time.
+	pub async fn keys(&self, prefix: &[u8], at: Hash) -> Vec<Vec<u8>> {
+		let mut keys: Vec<Vec<u8>> = vec![];
+		loop {
+			let start_key = keys.last().cloned().map(StorageKey);
+			let page = sub_storage::get_keys_paged(
+				StorageKey(prefix.to_vec()),
+				PAGE_SIZE,
+				start_key,
+				self.client,
+				at,
+			)
+			.await;
+			let is_full = page.len() == PAGE_SIZE as usize;
+			keys.extend(page.into_iter().map(|k| k.0));
+			if !is_full {
+				break keys;
+			}
+		}
+	}
+
+	/// All pairs under `prefix`.
+	pub async fn pairs(&mut self, prefix: &[u8], at: Hash) -> Vec<(Vec<u8>, Vec<u8>)> {
+		if !self.unsafe_denied {
+			let key = StorageKey(prefix.to_vec());
+			match sub_storage::try_get_pairs(key, self.client, at).await {
+				Ok(pairs) => return pairs.into_iter().map(|(k, v)| (k.0, v.0)).collect(),
+				Err(why) => self.deny("state_getPairs", why),
+			}
+		}
+
+		let mut pairs = vec![];
+		for key in self.keys(prefix, at).await {
+			let value = sub_storage::get_storage(StorageKey(key.clone()), self.client, at).await;
+			pairs.push((key, value.map(|v| v.0).unwrap_or_default()));
+		}
+		pairs
+	}
+
+	/// The size of all values under `prefix`, and their number if the keys had to be enumerated.
+	pub async fn size(&mut self, prefix: &[u8], at: Hash) -> (usize, Option<usize>) {
+		if !self.unsafe_denied {
+			let key = StorageKey(prefix.to_vec());
+			match sub_storage::try_get_storage_size(key, self.client, at).await {
+				Ok(size) => return (size.unwrap_or_default() as usize, None),
+				Err(why) => self.deny("state_getStorageSize", why),
+			}
+		}
+
+		let keys = self.keys(prefix, at).await;
+		let mut size = 0;
+		for key in keys.iter() {
+			let key = StorageKey(key.clone());
+			size += sub_storage::get_storage_size(key, self.client, at).await.unwrap_or_default();
+		}
+		(size as usize, Some(keys.len()))
+	}
+
+	/// The pallets that own default child tries, by the names of the tries.
+	///
+	/// Only the tries of `Crowdloan` and `Contracts` are known, as their names are derived from
+	/// their storage.
+	pub async fn child_trie_owners(&mut self, at: Hash) -> BTreeMap<Vec<u8>, &'static str> {
+		let mut owners = BTreeMap::new();
+
+		let next_index = sub_storage::value_key(b"Crowdloan", b"NextTrieIndex");
+		let next_index = sub_storage::read::<u32>(next_index, self.client, at).await;
+		for index in 0..next_index.unwrap_or_default() {
+			owners.insert(crowdloan_trie(index), "Crowdloan");
+		}
+
+		let contracts = sub_storage::module_prefix_raw(b"Contracts", b"ContractInfoOf");
+		for (_, info) in self.pairs(&contracts, at).await {
+			if let Some(name) = contract_trie(&info) {
+				owners.insert(name, "Contracts");
+			}
+		}
+
+		owners
+	}
+
+	/// All keys of the child trie `child_key`, enumerated one page at a time.
+	pub async fn child_keys(&self, child_key: &[u8], at: Hash) -> Vec<Vec<u8>> {
+		let mut keys: Vec<Vec<u8>> = vec![];
+		loop {
+			let start_key = keys.last().cloned().map(StorageKey);
+			let page = sub_storage::get_child_keys_paged(
+				StorageKey(child_key.to_vec()),
+				StorageKey(vec![]),
+				PAGE_SIZE,
+				start_key,
+				self.client,
+				at,
+			)
+			.await;
+			let is_full = page.len() == PAGE_SIZE as usize;
+			keys.extend(page.into_iter().map(|k| k.0));
+			if !is_full {
+				break keys;
+			}
+		}
+	}
+
+	/// All default child tries, with their sizes.
+	///
+	/// The size of each value of a child trie is queried on its own, i.e. this takes one RPC call
+	/// per key.
+	pub async fn child_tries(&self, at: Hash) -> Vec<ChildTrie> {
+		let mut tries = vec![];
+		for child_key in self.keys(CHILD_STORAGE_PREFIX, at).await {
+			let keys = self.child_keys(&child_key, at).await;
+			let child = StorageKey(child_key.clone());
+			let mut bytes = 0;
+			for key in keys.iter() {
+				bytes += sub_storage::get_child_storage_size(
+					child.clone(),
+					StorageKey(key.clone()),
+					self.client,
+					at,
+				)
+				.await
+				.unwrap_or_default();
+			}
+			let name = child_key[CHILD_STORAGE_PREFIX.len()..].to_vec();
+			tries.push(ChildTrie { name, bytes: bytes as usize, keys: keys.len() });
+		}
+		tries
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn trie(name: &[u8]) -> ChildTrie {
+		ChildTrie { name: name.to_vec(), bytes: 0, keys: 0 }
+	}
+
+	#[test]
+	fn crowdloan_trie_works() {
+		let mut name = b"crowdloan".to_vec();
+		name.extend_from_slice(&[7, 0, 0, 0]);
+		assert_eq!(crowdloan_trie(7), blake2_256(&name).to_vec());
+		assert_ne!(crowdloan_trie(7), crowdloan_trie(8));
+		assert_eq!(crowdloan_trie(7).len(), 32);
+	}
+
+	#[test]
+	fn contract_trie_works() {
+		let trie_id = vec![42u8; 32];
+		let rest = [5u8; 40];
+
+		// an alive contract of an older runtime.
+		let alive = [&[0u8][..], &trie_id.encode(), &rest].concat();
+		assert_eq!(contract_trie(&alive), Some(trie_id.clone()));
+		// a tombstone of an older runtime.
+		let tombstone = [&[1u8][..], &rest].concat();
+		assert_eq!(contract_trie(&tombstone), None);
+		// a contract of a newer runtime.
+		let info = [&trie_id.encode()[..], &rest].concat();
+		assert_eq!(contract_trie(&info), Some(trie_id));
+		assert_eq!(contract_trie(&[]), None);
+	}
+
+	#[test]
+	fn unsafe_denial_is_detected() {
+		let error = |code, message: &str| {
+			RpcError::Request(JsonRpcError { code, message: message.into(), data: None })
+		};
+		assert!(is_unsafe_denial(&error(ErrorCode::MethodNotFound, UNSAFE_RPC_MESSAGE)));
+		assert!(!is_unsafe_denial(&error(ErrorCode::MethodNotFound, "Method not found")));
+		assert!(!is_unsafe_denial(&error(ErrorCode::InternalError, UNSAFE_RPC_MESSAGE)));
+		assert!(!is_unsafe_denial(&error(ErrorCode::ServerError(-32000), "Client error")));
+	}
+
+	#[test]
+	fn child_trie_item_name_works() {
+		let name = crowdloan_trie(0);
+		assert_eq!(
+			trie(&name).item_name(),
+			format!(":child_storage:0x{}", HexDisplay::from(&name.as_slice()))
+		);
+		assert_eq!(trie(b"\x01\x02").item_name(), ":child_storage:0x0102");
+		assert_eq!(trie(b"a\x02").item_name(), ":child_storage:0x6102");
+		assert_eq!(trie(b"abc").item_name(), ":child_storage:abc");
+	}
+}
@@ -0,0 +1,204 @@
+//! The growth of the storage over a range of blocks.
+
+use crate::output::{Size, Snapshot};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use sub_storage::{Client, Hash};
+
+/// The block number type of polkadot-like chains.
+pub type BlockNumber = u32;
+
+/// The number of fastest growing items that are flagged.
+const FLAGGED: usize = 5;
+
+#[derive(Deserialize)]
+struct Header {
+	number: sp_core::U256,
+}
+
+/// The number of the block `at`.
+pub async fn block_number(client: &Client, at: Hash) -> BlockNumber {
+	let header = sub_storage::get_header::<Header>(client, at)
+		.await
+		.unwrap_or_else(|| panic!("Header of block {:?} must exist", at));
+	header.number.low_u32()
+}
+
+/// The numbers of the blocks to sample: every `step` blocks from `from`, and `to`.
+pub fn sample_numbers(from: BlockNumber, to: BlockNumber, step: BlockNumber) -> Vec<BlockNumber> {
+	assert!(from <= to, "--from block #{} is after the --to block #{}", from, to);
+	assert!(step > 0, "--step must not be zero");
+	let mut numbers = (from..to).step_by(step as usize).collect::<Vec<_>>();
+	numbers.push(to);
+	numbers
+}
+
+/// A sampled block.
+#[derive(Debug, Clone, Serialize)]
+pub struct Block {
+	pub number: BlockNumber,
+	pub hash: Hash,
+}
+
+/// The size of a storage item at each sampled block.
+#[derive(Debug, Clone, Serialize)]
+pub struct Series {
+	pub pallet: String,
+	pub item: String,
+	/// The bytes at each block, zero if the item did not exist.
+	pub bytes: Vec<usize>,
+	/// The average growth per block between the first and the last block.
+	pub bytes_per_block: f64,
+}
+
+impl Series {
+	/// The growth between the first and the last block, which is negative if the item shrunk.
+	pub fn growth(&self) -> i128 {
+		let first = self.bytes.first().copied().unwrap_or_default();
+		let last = self.bytes.last().copied().unwrap_or_default();
+		last as i128 - first as i128
+	}
+}
+
+/// The storage of a chain at a range of blocks.
+#[derive(Debug, Clone, Serialize)]
+pub struct History {
+	pub blocks: Vec<Block>,
+	/// The size of each item, from the fastest growing.
+	pub series: Vec<Series>,
+}
+
+impl History {
+	/// Build the history from the snapshots of the sampled blocks, in ascending order.
+	pub fn new(samples: Vec<(BlockNumber, Snapshot)>) -> Self {
+		let mut sizes = BTreeMap::<(String, String), Vec<usize>>::new();
+		for (index, (_, snapshot)) in samples.iter().enumerate() {
+			for module in snapshot.modules.iter() {
+				for storage in module.items.iter() {
+					let key = (module.name.clone(), storage.name.clone());
+					let bytes = sizes.entry(key).or_insert_with(|| vec![0; samples.len()]);
+					bytes[index] = storage.size();
+				}
+			}
+		}
+
+		let span = match (samples.first(), samples.last()) {
+			(Some((first, _)), Some((last, _))) => last - first,
+			_ => 0,
+		};
+		let mut series = sizes
+			.into_iter()
+			.map(|((pallet, item), bytes)| {
+				let mut series = Series { pallet, item, bytes, bytes_per_block: 0.0 };
+				if span > 0 {
+					series.bytes_per_block = series.growth() as f64 / span as f64;
+				}
+				series
+			})
+			.collect::<Vec<_>>();
+		series.sort_by_key(|s| std::cmp::Reverse(s.growth()));
+
+		let blocks = samples
+			.into_iter()
+			.map(|(number, snapshot)| Block { number, hash: snapshot.at })
+			.collect();
+		Self { blocks, series }
+	}
+
+	/// The items that grew the fastest.
+	pub fn fastest(&self) -> impl Iterator<Item = &Series> {
+		self.series.iter().take(FLAGGED).filter(|s| s.growth() > 0)
+	}
+
+	/// One line per storage item, with one column per block.
+	pub fn to_csv(&self) -> String {
+		let numbers = self.blocks.iter().map(|b| format!(",#{}", b.number)).collect::<String>();
+		let mut csv = format!("pallet,item{},bytes_per_block\n", numbers);
+		for series in self.series.iter() {
+			let bytes = series.bytes.iter().map(|b| format!(",{}", b)).collect::<String>();
+			csv.push_str(&format!(
+				"{},{}{},{}\n",
+				series.pallet, series.item, bytes, series.bytes_per_block
+			));
+		}
+		csv
+	}
+}
+
+impl std::fmt::Display for History {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let numbers = self.blocks.iter().map(|b| format!(" #{: <7}", b.number)).collect::<String>();
+		writeln!(f, "{: >16}{} item", "bytes/block", numbers)?;
+		for series in self.series.iter() {
+			let bytes =
+				series.bytes.iter().map(|b| format!("    {}", Size(*b))).collect::<String>();
+			writeln!(
+				f,
+				"{: >16.2}{} {}::{}",
+				series.bytes_per_block, bytes, series.pallet, series.item
+			)?;
+		}
+
+		writeln!(f, "Fastest growing items:")?;
+		for series in self.fastest() {
+			let first = series.bytes.first().copied().unwrap_or_default();
+			let percent = match first {
+				0 => "new".to_string(),
+				first => format!("{:+.2}%", series.growth() as f64 * 100.0 / first as f64),
+			};
+			writeln!(
+				f,
+				"  {}::{}: {:.2} bytes/block, {:+} bytes ({})",
+				series.pallet,
+				series.item,
+				series.bytes_per_block,
+				series.growth(),
+				percent,
+			)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::snapshot;
+
+	#[test]
+	fn sample_numbers_works() {
+		assert_eq!(sample_numbers(10, 10, 5), vec![10]);
+		assert_eq!(sample_numbers(10, 20, 5), vec![10, 15, 20]);
+		assert_eq!(sample_numbers(10, 22, 5), vec![10, 15, 20, 22]);
+	}
+
+	#[test]
+	fn history_works() {
+		let history = History::new(vec![
+			(100, snapshot(&[("System", "Account", 1000), ("System", "Events", 50)])),
+			(200, snapshot(&[("System", "Account", 1100), ("System", "Events", 10)])),
+			(
+				300,
+				snapshot(&[
+					("System", "Account", 1200),
+					("System", "Events", 0),
+					("System", "Proxies", 3000),
+				]),
+			),
+		]);
+
+		let names = history.series.iter().map(|s| s.item.as_str()).collect::<Vec<_>>();
+		assert_eq!(names, vec!["Proxies", "Account", "Events"]);
+		assert_eq!(history.series[0].bytes, vec![0, 0, 3000]);
+		assert_eq!(history.series[0].bytes_per_block, 15.0);
+		assert_eq!(history.series[1].bytes_per_block, 1.0);
+		assert_eq!(history.series[2].growth(), -50);
+
+		// shrinking items are never flagged.
+		assert_eq!(history.fastest().count(), 2);
+		assert_eq!(
+			history.to_csv().lines().take(2).collect::<Vec<_>>(),
+			vec!["pallet,item,#100,#200,#300,bytes_per_block", "System,Proxies,0,0,3000,15"],
+		);
+	}
+}
@@ -1,43 +1,31 @@
 use ansi_term::{Colour::*, Style};
 use frame_metadata::{RuntimeMetadata, RuntimeMetadataPrefixed, StorageEntryType};
 use separator::Separatable;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use structopt::StructOpt;
-use sub_storage::{get_head, get_metadata, unwrap_decoded, Hash, StorageKey};
+use sub_storage::{get_head, get_metadata, unwrap_decoded, Hash};
 
-const KB: usize = 1024;
-const MB: usize = KB * KB;
-const GB: usize = MB * MB;
+mod accounts;
+mod fetch;
+mod growth;
+mod output;
+mod stats;
+#[cfg(test)]
+mod testing;
 
-pub const LOG_TARGET: &'static str = "sub-du";
-
-fn get_prefix(indent: usize) -> &'static str {
-	match indent {
-		1 => "├─┬",
-		2 => "│ │─┬",
-		3 => "│ │ │─",
-		_ => panic!("Unexpected indent."),
-	}
-}
-
-struct Size(usize);
-
-impl std::fmt::Display for Size {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		if self.0 <= KB {
-			write!(f, "{: <4}B", self.0)?;
-		} else if self.0 <= MB {
-			write!(f, "{: <4}K", self.0 / KB)?;
-		} else if self.0 <= GB {
-			write!(f, "{: <4}M", self.0 / MB)?;
-		}
+use fetch::Fetcher;
+use growth::{BlockNumber, History};
+use output::{get_prefix, Format, Size, Snapshot};
+use stats::Stats;
 
-		Ok(())
-	}
-}
+pub const LOG_TARGET: &'static str = "sub-du";
 
-#[derive(Debug, Clone, Default)]
-struct Module {
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Module {
 	pub name: String,
+	/// The number of bytes of the values of all items.
+	#[serde(rename = "value_bytes")]
 	pub size: usize,
 	pub items: Vec<Storage>,
 }
@@ -53,7 +41,14 @@ impl std::fmt::Display for Module {
 			mod_style.paint(self.name.clone())
 		)?;
 		for s in self.items.iter() {
-			write!(f, "{} {} {}\n", Size(s.size), get_prefix(3), s)?;
+			write!(f, "{} {} {}\n", Size(s.size()), get_prefix(3), s)?;
+			if let Some(stats) = &s.stats {
+				writeln!(f, "{: <5} {} {}", "", get_prefix(4), stats)?;
+				for entry in stats.largest.iter() {
+					let bytes = entry.bytes.separated_string();
+					writeln!(f, "{: <5} {} {} bytes: {}", "", get_prefix(4), bytes, entry.key)?;
+				}
+			}
 		}
 		Ok(())
 	}
@@ -65,34 +60,49 @@ impl Module {
 	}
 }
 
-#[derive(Debug, Copy, Clone)]
+/// A storage item and the size of its values, which does not include its keys, so that it is the
+/// same whether the pairs were scraped or not. The number of keys of a map is only known if its
+/// pairs were scraped.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
 pub enum StorageItem {
-	Value(usize),
-	Map(usize, usize),
+	Value {
+		#[serde(rename = "value_bytes")]
+		bytes: usize,
+	},
+	Map {
+		#[serde(rename = "value_bytes")]
+		bytes: usize,
+		keys: Option<usize>,
+	},
 }
 
 impl std::fmt::Display for StorageItem {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
-			Self::Value(bytes) => write!(f, "Value({} bytes)", bytes.separated_string()),
-			Self::Map(bytes, count) => {
-				write!(f, "Map({} bytes, {} keys)", bytes.separated_string(), count)
+			Self::Value { bytes } => write!(f, "Value({} bytes)", bytes.separated_string()),
+			Self::Map { bytes, keys: Some(keys) } => {
+				write!(f, "Map({} bytes, {} keys)", bytes.separated_string(), keys)
 			}
+			Self::Map { bytes, keys: None } => write!(f, "Map({} bytes)", bytes.separated_string()),
 		}
 	}
 }
 
 impl Default for StorageItem {
 	fn default() -> Self {
-		Self::Value(0)
+		Self::Value { bytes: 0 }
 	}
 }
 
-#[derive(Debug, Clone, Default)]
-struct Storage {
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Storage {
 	pub name: String,
-	pub size: usize,
+	#[serde(flatten)]
 	pub item: StorageItem,
+	/// The statistics of the entries of a map, if its pairs were scraped.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub stats: Option<Stats>,
 }
 
 impl std::fmt::Display for Storage {
@@ -104,11 +114,22 @@ impl std::fmt::Display for Storage {
 
 impl Storage {
 	fn new(name: String, item: StorageItem) -> Self {
-		let size = match item {
-			StorageItem::Value(s) => s,
-			StorageItem::Map(s, _) => s,
-		};
-		Self { name, item, size }
+		Self { name, item, stats: None }
+	}
+
+	pub fn size(&self) -> usize {
+		match self.item {
+			StorageItem::Value { bytes } => bytes,
+			StorageItem::Map { bytes, .. } => bytes,
+		}
+	}
+
+	/// The number of keys, which is 1 for values.
+	pub fn keys(&self) -> Option<usize> {
+		match self.item {
+			StorageItem::Value { .. } => Some(1),
+			StorageItem::Map { keys, .. } => keys,
+		}
 	}
 }
 
@@ -138,31 +159,98 @@ struct Opt {
 	///
 	/// # Warning
 	///
-	/// This uses an unsafe RPC call and can only be used if the target node allows it.
+	/// This uses an unsafe RPC call. If the target node denies it, all keys are enumerated and
+	/// their values are fetched one by one instead, which is much slower.
 	#[structopt(long, short)]
 	scrape_pairs: bool,
-}
 
-#[async_std::main]
-async fn main() -> () {
-	env_logger::Builder::from_default_env().format_module_path(false).format_level(true).init();
+	/// The number of largest entries to report for each map whose pairs are scraped. Their keys
+	/// are decoded if the hashers and key types of the map allow it.
+	#[structopt(long, default_value = "3")]
+	largest: usize,
 
-	let opt = Opt::from_args();
+	/// Also report the given number of accounts that use the most storage.
+	///
+	/// The accounts are decoded from the keys of all maps that are keyed by an account with a
+	/// concatenating hasher, such as `System::Account` or `Staking::Ledger`. Both the keys and
+	/// values of their entries are attributed to the account.
+	///
+	/// # Warning
+	///
+	/// Like `--scrape-pairs`, this scrapes all pairs of these maps.
+	#[structopt(long)]
+	accounts: Option<usize>,
 
-	// connect to a node.
-	use jsonrpsee_ws_client::{WsClient, WsConfig};
-	let client = WsClient::new(&opt.uri, WsConfig::default()).await.unwrap();
+	/// Also report the default child tries, e.g. of crowdloans and contracts, under the pallets
+	/// that own them.
+	///
+	/// # Warning
+	///
+	/// All keys of the child tries are enumerated, and the size of each value is queried on its
+	/// own, which takes one RPC call per key.
+	#[structopt(long)]
+	child_tries: bool,
 
-	let mut modules: Vec<Module> = vec![];
+	/// The format of the output: a `tree`, or the exact byte and key counts as `json` or `csv`.
+	///
+	/// The sizes are those of the values only. The bytes of the keys of a map, `key_bytes`, are
+	/// only reported alongside its statistics, i.e. if its pairs are scraped, e.g. with
+	/// `--scrape-pairs`. Otherwise, its keys are not counted at all.
+	///
+	/// With `json` or `csv`, the progress messages are printed to stderr, so that stdout only
+	/// contains the output. The `json` output can be compared with the `diff` command.
+	#[structopt(long, default_value = "tree")]
+	format: Format,
+
+	/// Sample the storage every `--step` blocks, from this block to the `--to` block, and report
+	/// the growth of each item.
+	#[structopt(long, conflicts_with = "at")]
+	from: Option<Hash>,
+
+	/// The last block to sample with `--from`. Defaults to the finalized head.
+	#[structopt(long, requires = "from")]
+	to: Option<Hash>,
+
+	/// The number of blocks between two samples of `--from`.
+	#[structopt(long, default_value = "14400")]
+	step: BlockNumber,
+
+	#[structopt(subcommand)]
+	command: Option<Command>,
+}
 
-	// potentially replace head with the given hash
-	let head = get_head(&client).await;
-	let at = opt.at.unwrap_or(head);
-	let runtime = sub_storage::get_runtime_version(&client, at).await;
+#[derive(Debug, StructOpt)]
+enum Command {
+	/// Show the storage growth of each item between two `--format json` outputs.
+	Diff {
+		/// The output at the older block.
+		from: PathBuf,
+		/// The output at the newer block.
+		to: PathBuf,
+	},
+}
 
-	println!("Scraping at block {:?} of {}({})", at, runtime.spec_name, runtime.spec_version,);
+/// Print a progress message, to stderr if stdout is reserved for the output.
+fn status(format: Format, message: impl std::fmt::Display) {
+	match format {
+		Format::Tree => println!("{}", message),
+		Format::Json | Format::Csv => eprintln!("{}", message),
+	}
+}
+
+/// Scrape the storage of all pallets at block `at`, including their child tries if enabled.
+async fn scrape(fetcher: &mut Fetcher<'_>, at: Hash, opt: &Opt) -> Snapshot {
+	let client = fetcher.client;
+	let mut modules: Vec<Module> = vec![];
+	let mut footprints = accounts::Footprints::default();
+
+	let runtime = sub_storage::get_runtime_version(client, at).await;
+	status(
+		opt.format,
+		format!("Scraping at block {:?} of {}({})", at, runtime.spec_name, runtime.spec_version),
+	);
 
-	let raw_metadata = get_metadata(&client, at).await.0;
+	let raw_metadata = get_metadata(client, at).await.0;
 	let prefixed_metadata = <RuntimeMetadataPrefixed as codec::Decode>::decode(&mut &*raw_metadata)
 		.expect("Runtime Metadata failed to decode");
 	let metadata = prefixed_metadata.1;
@@ -192,63 +280,169 @@ async fn main() -> () {
 				let ty = storage_entry.ty;
 				let key_prefix =
 					sub_storage::module_prefix_raw(prefix.as_bytes(), storage_name.as_bytes());
+				let account_offset = opt.accounts.and(accounts::account_offset(&ty));
 
-				let (pairs, size) = if opt.scrape_pairs {
+				let (pairs, size, count) = if opt.scrape_pairs || account_offset.is_some() {
 					// this should be slower but gives more detail.
-					let pairs =
-						sub_storage::get_pairs(StorageKey(key_prefix.clone()), &client, at).await;
-					let pairs = pairs
-						.into_iter()
-						.map(|(k, v)| (k.0, v.0))
-						.collect::<Vec<(Vec<u8>, Vec<u8>)>>();
+					let pairs = fetcher.pairs(&key_prefix, at).await;
 					let size = pairs.iter().fold(0, |acc, x| acc + x.1.len());
-					(pairs, size)
+					let count = pairs.len();
+					(Some(pairs), size, Some(count))
 				} else {
 					// This should be faster
-					let size = sub_storage::get_storage_size(StorageKey(key_prefix), &client, at)
-						.await
-						.unwrap_or_default() as usize;
-					let pairs: Vec<_> = vec![];
-					(pairs, size)
+					let (size, count) = fetcher.size(&key_prefix, at).await;
+					(None, size, count)
 				};
 
 				log::debug!(
 					target: LOG_TARGET,
-					"{:?}::{:?} => count: {}, size: {} bytes",
+					"{:?}::{:?} => count: {:?}, size: {} bytes",
 					name,
 					storage_name,
-					pairs.len(),
+					count,
 					size
 				);
 
+				if let (Some(offset), Some(pairs)) = (account_offset, pairs.as_ref()) {
+					let item_name = format!("{}::{}", name, storage_name);
+					for (key, value) in pairs.iter() {
+						if !footprints.record(&item_name, offset, key, value) {
+							log::warn!(
+								target: LOG_TARGET,
+								"{} has a key too short to contain an account: {:?}",
+								item_name,
+								key,
+							);
+						}
+					}
+				}
+
 				module_info.size += size;
 				let item = match ty {
-					StorageEntryType::Plain(_) => StorageItem::Value(size),
+					StorageEntryType::Plain(_) => StorageItem::Value { bytes: size },
 					StorageEntryType::Map { .. } | StorageEntryType::DoubleMap { .. } => {
-						StorageItem::Map(size, pairs.len())
+						StorageItem::Map { bytes: size, keys: count }
 					}
 				};
-				module_info.items.push(Storage::new(storage_name, item));
+				let mut storage = Storage::new(storage_name, item);
+				if let (StorageItem::Map { .. }, Some(pairs)) = (item, pairs.as_ref()) {
+					storage.stats = Stats::new(pairs, &ty, opt.largest);
+				}
+				module_info.items.push(storage);
 			}
-			module_info.items.sort_by_key(|x| x.size);
+			module_info.items.sort_by_key(|x| x.size());
 			module_info.items.reverse();
-			println!("Scraped module {}. Total size {}.", module_info.name, module_info.size,);
+			status(
+				opt.format,
+				format!("Scraped module {}. Total size {}.", module_info.name, module_info.size),
+			);
 			if opt.progress {
-				print!("{}", module_info);
+				status(opt.format, format!("{}", module_info).trim_end());
 			}
 			modules.push(module_info);
 		}
-
-		println!("Scraping results done. Final sorted tree:");
-		modules.sort_by_key(|m| m.size);
-		modules.reverse();
-
-		let total: usize = modules.iter().map(|m| m.size).sum();
-		println!("{} {} {}", Size(total), get_prefix(1), runtime.spec_name,);
-		modules.into_iter().for_each(|m| {
-			print!("{}", m);
-		});
 	} else {
 		panic!("Unsupported Metadata version");
 	}
+
+	let tries = if opt.child_tries { fetcher.child_tries(at).await } else { vec![] };
+	let owners =
+		if tries.is_empty() { Default::default() } else { fetcher.child_trie_owners(at).await };
+	for trie in tries {
+		let owner = owners.get(&trie.name).map(|o| o.to_string());
+		let owner = owner.unwrap_or_else(|| {
+			log::warn!(target: LOG_TARGET, "child trie {} has no owner.", trie.item_name());
+			String::from_utf8_lossy(fetch::CHILD_STORAGE_PREFIX).into_owned()
+		});
+		let module = match modules.iter().position(|m| m.name == owner) {
+			Some(index) => &mut modules[index],
+			None => {
+				modules.push(Module::new(owner));
+				modules.last_mut().expect("just pushed; qed")
+			}
+		};
+		let item = StorageItem::Map { bytes: trie.bytes, keys: Some(trie.keys) };
+		module.size += trie.bytes;
+		module.items.push(Storage::new(trie.item_name(), item));
+		module.items.sort_by_key(|x| std::cmp::Reverse(x.size()));
+	}
+
+	modules.sort_by_key(|m| m.size);
+	modules.reverse();
+
+	Snapshot {
+		at,
+		spec_name: runtime.spec_name.to_string(),
+		spec_version: runtime.spec_version,
+		size: modules.iter().map(|m| m.size).sum(),
+		modules,
+		accounts: opt.accounts.map(|count| footprints.top(count)).unwrap_or_default(),
+	}
+}
+
+#[async_std::main]
+async fn main() -> () {
+	env_logger::Builder::from_default_env().format_module_path(false).format_level(true).init();
+
+	let opt = Opt::from_args();
+
+	if let Some(Command::Diff { from, to }) = &opt.command {
+		let (from, to) = (Snapshot::load(from), Snapshot::load(to));
+		print!("{}", output::Diff::new(&from, &to));
+		return;
+	}
+
+	// connect to a node.
+	use jsonrpsee_ws_client::{WsClient, WsConfig};
+	let client = WsClient::new(&opt.uri, WsConfig::default()).await.unwrap();
+
+	let mut fetcher = Fetcher::new(&client);
+
+	// potentially replace head with the given hash
+	let head = get_head(&client).await;
+
+	if let Some(from) = opt.from {
+		let to = opt.to.unwrap_or(head);
+		let (from_number, to_number) =
+			(growth::block_number(&client, from).await, growth::block_number(&client, to).await);
+		let mut samples = vec![];
+		for number in growth::sample_numbers(from_number, to_number, opt.step) {
+			// the given blocks are used as-is, even if they are not canonical.
+			let at = match number {
+				n if n == from_number => from,
+				n if n == to_number => to,
+				n => sub_storage::get_block_hash(&client, n)
+					.await
+					.unwrap_or_else(|| panic!("Block #{} must exist", n)),
+			};
+			samples.push((number, scrape(&mut fetcher, at, &opt).await));
+		}
+
+		let history = History::new(samples);
+		match opt.format {
+			Format::Tree => print!("{}", history),
+			Format::Json => {
+				println!(
+					"{}",
+					serde_json::to_string_pretty(&history).expect("Output is valid json")
+				)
+			}
+			Format::Csv => print!("{}", history.to_csv()),
+		}
+		return;
+	}
+
+	let at = opt.at.unwrap_or(head);
+
+	let snapshot = scrape(&mut fetcher, at, &opt).await;
+	match opt.format {
+		Format::Tree => {
+			println!("Scraping results done. Final sorted tree:");
+			print!("{}", snapshot);
+		}
+		Format::Json => {
+			println!("{}", serde_json::to_string_pretty(&snapshot).expect("Output is valid json"))
+		}
+		Format::Csv => print!("{}", snapshot.to_csv()),
+	}
 }
@@ -0,0 +1,296 @@
+//! The output formats of sub-du, and the comparison of two outputs.
+
+use crate::{accounts::AccountFootprint, Module, StorageItem};
+use separator::Separatable;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+use sub_storage::Hash;
+
+const KB: usize = 1024;
+const MB: usize = KB * KB;
+const GB: usize = MB * KB;
+
+pub fn get_prefix(indent: usize) -> &'static str {
+	match indent {
+		1 => "├─┬",
+		2 => "│ │─┬",
+		3 => "│ │ │─",
+		4 => "│ │ │ ",
+		_ => panic!("Unexpected indent."),
+	}
+}
+
+/// A number of bytes, rounded down to the largest unit that it exceeds.
+pub struct Size(pub usize);
+
+impl std::fmt::Display for Size {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if self.0 <= KB {
+			write!(f, "{: <4}B", self.0)
+		} else if self.0 <= MB {
+			write!(f, "{: <4}K", self.0 / KB)
+		} else if self.0 <= GB {
+			write!(f, "{: <4}M", self.0 / MB)
+		} else {
+			write!(f, "{: <4}G", self.0 / GB)
+		}
+	}
+}
+
+/// The format in which the storage of a chain is printed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+	/// A human readable tree, with rounded sizes.
+	Tree,
+	/// A [`Snapshot`] in json.
+	Json,
+	/// One line per storage item.
+	Csv,
+}
+
+impl std::str::FromStr for Format {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"tree" => Ok(Self::Tree),
+			"json" => Ok(Self::Json),
+			"csv" => Ok(Self::Csv),
+			_ => Err("format must be one of `tree`, `json` or `csv`"),
+		}
+	}
+}
+
+/// The storage of a chain at a block, with exact byte counts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+	pub at: Hash,
+	pub spec_name: String,
+	pub spec_version: u32,
+	/// The number of bytes of the values of all items.
+	#[serde(rename = "value_bytes")]
+	pub size: usize,
+	/// The modules, from the largest.
+	#[serde(rename = "pallets")]
+	pub modules: Vec<Module>,
+	/// The accounts that use the most storage, if requested.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub accounts: Vec<AccountFootprint>,
+}
+
+impl Snapshot {
+	/// Load a snapshot that was printed with `--format json`.
+	pub fn load(path: &Path) -> Self {
+		let content = std::fs::read_to_string(path)
+			.unwrap_or_else(|why| panic!("Failed to read {:?}: {}", path, why));
+		serde_json::from_str(&content)
+			.unwrap_or_else(|why| panic!("{:?} is not a json output of sub-du: {}", path, why))
+	}
+
+	/// One line per storage item. The number of keys and the statistics of the entries are empty
+	/// if they are unknown.
+	pub fn to_csv(&self) -> String {
+		let mut csv =
+			String::from("pallet,item,kind,value_bytes,keys,key_bytes,min,mean,median,p99,max\n");
+		for module in self.modules.iter() {
+			for storage in module.items.iter() {
+				let kind = match storage.item {
+					StorageItem::Value { .. } => "value",
+					StorageItem::Map { .. } => "map",
+				};
+				let keys = storage.keys().map(|k| k.to_string()).unwrap_or_default();
+				let stats = match &storage.stats {
+					Some(s) => format!(
+						"{},{},{},{},{},{}",
+						s.key_bytes, s.min, s.mean, s.median, s.p99, s.max
+					),
+					None => ",,,,,".to_string(),
+				};
+				csv.push_str(&format!(
+					"{},{},{},{},{},{}\n",
+					module.name,
+					storage.name,
+					kind,
+					storage.size(),
+					keys,
+					stats,
+				));
+			}
+		}
+		csv
+	}
+}
+
+impl std::fmt::Display for Snapshot {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "{} {} {}", Size(self.size), get_prefix(1), self.spec_name)?;
+		for module in self.modules.iter() {
+			write!(f, "{}", module)?;
+		}
+		if !self.accounts.is_empty() {
+			writeln!(f, "Top {} accounts by storage footprint:", self.accounts.len())?;
+			for account in self.accounts.iter() {
+				writeln!(f, "{}", account)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// The growth of a storage item between two snapshots.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Growth {
+	pub module: String,
+	pub item: String,
+	pub from: usize,
+	pub to: usize,
+}
+
+impl Growth {
+	/// The number of bytes by which the item grew, which is negative if it shrunk.
+	pub fn delta(&self) -> i128 {
+		self.to as i128 - self.from as i128
+	}
+
+	/// The relative growth, if the item existed before.
+	pub fn percent(&self) -> Option<f64> {
+		(self.from > 0).then(|| self.delta() as f64 * 100.0 / self.from as f64)
+	}
+}
+
+/// The growth of all storage items between two snapshots.
+pub struct Diff<'a> {
+	from: &'a Snapshot,
+	to: &'a Snapshot,
+	/// The items that changed, from the largest absolute change.
+	pub changed: Vec<Growth>,
+	/// The number of items that did not change.
+	pub unchanged: usize,
+}
+
+impl<'a> Diff<'a> {
+	/// Compare `from` with `to`. Items that exist in only one of them are considered empty in the
+	/// other one.
+	pub fn new(from: &'a Snapshot, to: &'a Snapshot) -> Self {
+		let mut sizes = BTreeMap::<(String, String), (usize, usize)>::new();
+		for (snapshot, is_to) in [(from, false), (to, true)] {
+			for module in snapshot.modules.iter() {
+				for storage in module.items.iter() {
+					let key = (module.name.clone(), storage.name.clone());
+					let entry = sizes.entry(key).or_default();
+					if is_to {
+						entry.1 = storage.size();
+					} else {
+						entry.0 = storage.size();
+					}
+				}
+			}
+		}
+
+		let (mut changed, unchanged): (Vec<_>, Vec<_>) = sizes
+			.into_iter()
+			.map(|((module, item), (from, to))| Growth { module, item, from, to })
+			.partition(|g| g.delta() != 0);
+		changed.sort_by_key(|g| std::cmp::Reverse(g.delta().abs()));
+		Self { from, to, changed, unchanged: unchanged.len() }
+	}
+}
+
+impl std::fmt::Display for Diff<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(
+			f,
+			"Storage growth from block {:?} of {}({}) to block {:?} of {}({}):",
+			self.from.at,
+			self.from.spec_name,
+			self.from.spec_version,
+			self.to.at,
+			self.to.spec_name,
+			self.to.spec_version,
+		)?;
+		for growth in self.changed.iter() {
+			let percent = match growth.percent() {
+				Some(percent) => format!("{:+.2}%", percent),
+				None => "new".to_string(),
+			};
+			writeln!(
+				f,
+				"{: >16} {: >10} {}::{} ({} -> {} bytes)",
+				format!("{:+}", growth.delta()),
+				percent,
+				growth.module,
+				growth.item,
+				growth.from.separated_string(),
+				growth.to.separated_string(),
+			)?;
+		}
+		writeln!(f, "{} items unchanged.", self.unchanged)?;
+		writeln!(
+			f,
+			"Total: {:+} bytes ({} -> {} bytes)",
+			self.to.size as i128 - self.from.size as i128,
+			self.from.size.separated_string(),
+			self.to.size.separated_string(),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{testing::snapshot, Storage};
+
+	#[test]
+	fn size_display_works() {
+		assert_eq!(Size(1000).to_string(), "1000B");
+		assert_eq!(Size(2 * KB).to_string(), "2   K");
+		assert_eq!(Size(3 * MB).to_string(), "3   M");
+		assert_eq!(Size(5 * GB).to_string(), "5   G");
+	}
+
+	#[test]
+	fn csv_works() {
+		let mut s = snapshot(&[("System", "Account", 100)]);
+		s.modules[0].items.push(Storage::new("Number".into(), StorageItem::Value { bytes: 4 }));
+		assert_eq!(
+			s.to_csv(),
+			"pallet,item,kind,value_bytes,keys,key_bytes,min,mean,median,p99,max\n\
+			 System,Account,map,100,,,,,,,\n\
+			 System,Number,value,4,1,,,,,,\n"
+		);
+	}
+
+	#[test]
+	fn json_is_lossless() {
+		let mut s = snapshot(&[("System", "Account", 100), ("Staking", "Ledger", 3 * GB)]);
+		s.modules[0].items[0].item = StorageItem::Map { bytes: 3 * GB, keys: Some(7) };
+		let json = serde_json::to_string(&s).unwrap();
+		assert!(json.contains(r#""kind":"map","value_bytes":3221225472,"keys":7"#));
+		let back: Snapshot = serde_json::from_str(&json).unwrap();
+		assert_eq!(back.size, s.size);
+		assert_eq!(back.modules[0].items[0].item, s.modules[0].items[0].item);
+		assert_eq!(back.modules[1].items[0].item, s.modules[1].items[0].item);
+	}
+
+	#[test]
+	fn diff_works() {
+		let from = snapshot(&[("System", "Account", 100), ("Staking", "Ledger", 50)]);
+		let to = snapshot(&[
+			("System", "Account", 150),
+			("Staking", "Ledger", 50),
+			("Proxy", "Proxies", 500),
+		]);
+		let diff = Diff::new(&from, &to);
+		assert_eq!(diff.unchanged, 1);
+		assert_eq!(
+			diff.changed.iter().map(|g| (g.item.as_str(), g.delta())).collect::<Vec<_>>(),
+			vec![("Proxies", 500), ("Account", 50)],
+		);
+		assert_eq!(diff.changed[0].percent(), None);
+		assert_eq!(diff.changed[1].percent(), Some(50.0));
+
+		// shrinking items are reported too.
+		let diff = Diff::new(&to, &from);
+		assert_eq!(diff.changed[0].delta(), -500);
+	}
+}
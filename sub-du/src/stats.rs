@@ -0,0 +1,198 @@
+//! The distribution of the key and value sizes of a storage map, and its largest entries.
+
+use crate::{
+	accounts::{
+		concat_offset, fixed_size, is_account, type_name, AccountId, ACCOUNT_LEN, PREFIX_LEN,
+	},
+	output::Size,
+};
+use frame_metadata::StorageEntryType;
+use serde::{Deserialize, Serialize};
+use sp_core::hexdisplay::HexDisplay;
+
+/// A single entry of a map.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+	/// The decoded key if the hashers allow it, else the key without its prefix, in hex.
+	pub key: String,
+	/// The number of bytes of the value.
+	pub bytes: usize,
+}
+
+/// The statistics of the entries of a map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+	/// The number of bytes of all keys.
+	pub key_bytes: usize,
+	/// The number of bytes of all values.
+	pub value_bytes: usize,
+	pub min: usize,
+	pub mean: f64,
+	pub median: usize,
+	pub p99: usize,
+	pub max: usize,
+	/// The largest entries, from the largest.
+	pub largest: Vec<Entry>,
+}
+
+/// The nearest-rank `percent` percentile of the non-empty `sorted`.
+fn percentile(sorted: &[usize], percent: usize) -> usize {
+	let rank = (percent * sorted.len() + 99) / 100;
+	sorted[rank.max(1) - 1]
+}
+
+impl Stats {
+	/// The statistics of the full keys and values in `pairs` of a map of type `ty`, with its
+	/// `largest` entries. Returns `None` if the map is empty.
+	pub fn new(
+		pairs: &[(Vec<u8>, Vec<u8>)],
+		ty: &StorageEntryType,
+		largest: usize,
+	) -> Option<Self> {
+		if pairs.is_empty() {
+			return None;
+		}
+
+		let mut sizes = pairs.iter().map(|(_, v)| v.len()).collect::<Vec<_>>();
+		sizes.sort_unstable();
+		let value_bytes = sizes.iter().sum::<usize>();
+
+		let mut by_size = pairs.iter().collect::<Vec<_>>();
+		by_size.sort_by_key(|(_, v)| std::cmp::Reverse(v.len()));
+		let largest = by_size
+			.into_iter()
+			.take(largest)
+			.map(|(k, v)| Entry { key: decode_key(ty, k), bytes: v.len() })
+			.collect();
+
+		Some(Self {
+			key_bytes: pairs.iter().map(|(k, _)| k.len()).sum(),
+			value_bytes,
+			min: sizes[0],
+			mean: value_bytes as f64 / sizes.len() as f64,
+			median: percentile(&sizes, 50),
+			p99: percentile(&sizes, 99),
+			max: sizes[sizes.len() - 1],
+			largest,
+		})
+	}
+}
+
+impl std::fmt::Display for Stats {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"keys {}, values {}, value size min {} / mean {:.1} / median {} / p99 {} / max {}",
+			Size(self.key_bytes).to_string().trim_end(),
+			Size(self.value_bytes).to_string().trim_end(),
+			self.min,
+			self.mean,
+			self.median,
+			self.p99,
+			self.max,
+		)
+	}
+}
+
+/// Render the encoded key `encoded` of type `ty`.
+fn render(ty: &str, encoded: &[u8]) -> String {
+	match fixed_size(ty) {
+		Some(size) if size == encoded.len() && is_account(ty) => {
+			let mut raw = [0u8; ACCOUNT_LEN];
+			raw.copy_from_slice(encoded);
+			AccountId::from(raw).to_string()
+		}
+		Some(size) if size == encoded.len() && size <= 16 => {
+			let mut le = [0u8; 16];
+			le[..size].copy_from_slice(encoded);
+			u128::from_le_bytes(le).to_string()
+		}
+		_ => format!("0x{}", HexDisplay::from(&encoded)),
+	}
+}
+
+/// Decode the `key` without prefix of a double map, if the first key has a fixed size.
+fn decode_double_map_key(ty: &StorageEntryType, key: &[u8]) -> Option<String> {
+	match ty {
+		StorageEntryType::DoubleMap { hasher, key1, key2_hasher, key2, .. } => {
+			let start = concat_offset(hasher)?;
+			let end = start + fixed_size(type_name(key1))?;
+			let first = render(type_name(key1), key.get(start..end)?);
+			let second = key.get(end + concat_offset(key2_hasher)?..)?;
+			Some(format!("({}, {})", first, render(type_name(key2), second)))
+		}
+		_ => None,
+	}
+}
+
+/// Decode the full `key` of a map of type `ty`, as far as its hashers allow it.
+fn decode_key(ty: &StorageEntryType, key: &[u8]) -> String {
+	let key = key.get(PREFIX_LEN..).unwrap_or_default();
+	let hex = || format!("0x{}", HexDisplay::from(&key));
+	let decoded = match ty {
+		StorageEntryType::Plain(_) => None,
+		StorageEntryType::Map { hasher, key: key_ty, .. } => concat_offset(hasher)
+			.and_then(|offset| key.get(offset..))
+			.map(|encoded| render(type_name(key_ty), encoded)),
+		StorageEntryType::DoubleMap { .. } => decode_double_map_key(ty, key),
+	};
+	decoded.unwrap_or_else(hex)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{double_map, key, map};
+	use frame_metadata::StorageHasher;
+
+	#[test]
+	fn percentile_works() {
+		let sorted = (1..=100).collect::<Vec<_>>();
+		assert_eq!(percentile(&sorted, 50), 50);
+		assert_eq!(percentile(&sorted, 99), 99);
+		assert_eq!(percentile(&[7], 50), 7);
+		assert_eq!(percentile(&[1, 2, 3], 99), 3);
+	}
+
+	#[test]
+	fn keys_are_decoded() {
+		let ty = map(StorageHasher::Twox64Concat, "EraIndex");
+		assert_eq!(decode_key(&ty, &key(8, &[1, 1, 0, 0])), "257");
+
+		let ty = map(StorageHasher::Blake2_128Concat, "T::AccountId");
+		let account = AccountId::from([7u8; 32]);
+		assert_eq!(decode_key(&ty, &key(16, &[7u8; 32])), account.to_string());
+
+		// unknown types are shown in hex, and so are keys of opaque hashers.
+		let ty = map(StorageHasher::Twox64Concat, "Vec<u8>");
+		assert_eq!(decode_key(&ty, &key(8, &[4, 0xab])), "0x04ab");
+		let ty = map(StorageHasher::Blake2_256, "u32");
+		assert_eq!(decode_key(&ty, &key(0, &[0xff, 1])), "0xff01");
+
+		use StorageHasher::Twox64Concat;
+		let ty = double_map(Twox64Concat, "EraIndex", Twox64Concat, "T::AccountId");
+		let mut encoded = vec![3, 0, 0, 0];
+		encoded.extend(vec![0u8; 8]);
+		encoded.extend(vec![7u8; 32]);
+		assert_eq!(decode_key(&ty, &key(8, &encoded)), format!("(3, {})", account));
+	}
+
+	#[test]
+	fn stats_work() {
+		let ty = map(StorageHasher::Twox64Concat, "u32");
+		let pairs = (1u32..=10)
+			.map(|i| (key(8, &i.to_le_bytes()), vec![0u8; i as usize * 10]))
+			.collect::<Vec<_>>();
+		let stats = Stats::new(&pairs, &ty, 2).unwrap();
+
+		assert_eq!(stats.key_bytes, 10 * (PREFIX_LEN + 8 + 4));
+		assert_eq!(stats.value_bytes, 550);
+		assert_eq!((stats.min, stats.median, stats.p99, stats.max), (10, 50, 100, 100));
+		assert_eq!(stats.mean, 55.0);
+		assert_eq!(
+			stats.largest,
+			vec![Entry { key: "10".into(), bytes: 100 }, Entry { key: "9".into(), bytes: 90 }],
+		);
+		assert!(Stats::new(&[], &ty, 2).is_none());
+	}
+}
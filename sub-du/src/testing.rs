@@ -0,0 +1,59 @@
+//! Helpers shared by the tests of all modules.
+
+use crate::{accounts::PREFIX_LEN, output::Snapshot, Module, Storage, StorageItem};
+use frame_metadata::{DecodeDifferent, DecodeDifferentStr, StorageEntryType, StorageHasher};
+use std::collections::BTreeMap;
+use sub_storage::Hash;
+
+/// The type `name`, as decoded from the metadata of a node.
+pub fn ty(name: &str) -> DecodeDifferentStr {
+	DecodeDifferent::Decoded(name.to_string())
+}
+
+/// A map with keys of type `key`, and unit values.
+pub fn map(hasher: StorageHasher, key: &str) -> StorageEntryType {
+	StorageEntryType::Map { hasher, key: ty(key), value: ty("()"), unused: false }
+}
+
+/// A double map with keys of types `key1` and `key2`, and unit values.
+pub fn double_map(
+	hasher: StorageHasher,
+	key1: &str,
+	key2_hasher: StorageHasher,
+	key2: &str,
+) -> StorageEntryType {
+	StorageEntryType::DoubleMap {
+		hasher,
+		key1: ty(key1),
+		key2: ty(key2),
+		value: ty("()"),
+		key2_hasher,
+	}
+}
+
+/// A full key of a map, with an empty prefix and hash of `hash_len` bytes before `encoded`.
+pub fn key(hash_len: usize, encoded: &[u8]) -> Vec<u8> {
+	let mut key = vec![0u8; PREFIX_LEN + hash_len];
+	key.extend_from_slice(encoded);
+	key
+}
+
+/// A snapshot with the given `(pallet, item, bytes)` maps, whose number of keys is unknown.
+pub fn snapshot(items: &[(&str, &str, usize)]) -> Snapshot {
+	let mut modules = BTreeMap::<&str, Module>::new();
+	for (module, name, bytes) in items {
+		let module = modules.entry(*module).or_insert_with(|| Module::new(module.to_string()));
+		module.size += bytes;
+		let item = StorageItem::Map { bytes: *bytes, keys: None };
+		module.items.push(Storage::new(name.to_string(), item));
+	}
+	let modules = modules.into_values().collect::<Vec<_>>();
+	Snapshot {
+		at: Hash::repeat_byte(1),
+		spec_name: "node".to_string(),
+		spec_version: 1,
+		size: modules.iter().map(|m| m.size).sum(),
+		modules,
+		accounts: vec![],
+	}
+}
@@ -14,7 +14,8 @@
 //!
 //! The most useful features provided by this crate are often marked as unsafe by the substrate
 //! nodes. Namely, [`get_pairs`] and [`enumerate_map`] can only be used against nodes that such
-//! external RPCs.
+//! external RPCs. The `try_` variants of these calls return the error of the node instead, so that
+//! callers can fall back to safe calls such as [`get_keys_paged`].
 //!
 //! THIS IS A TEST.
 
@@ -33,6 +34,8 @@ pub mod helpers;
 
 /// re-export some stuff from sp-core.
 pub use sp_core::storage::{StorageData, StorageKey};
+/// The error of a failed RPC call.
+pub use jsonrpsee_types::error::Error as RpcError;
 /// The hash type used by this crate.
 pub type Hash = sp_core::hash::H256;
 // TODO: write a basic abstraction above the two?
@@ -99,14 +102,39 @@ pub fn module_prefix_raw(module: &[u8], storage: &[u8]) -> Vec<u8> {
 /// Read from a raw key regardless of the type. This can be used in combination with the key
 /// generation methods above and read any data from storage, regardless of its type.
 pub async fn read<T: Decode>(key: StorageKey, client: &Client, at: Hash) -> Option<T> {
+	let encoded = get_storage(key, client, at).await.map(|d| d.0)?;
+	<T as Decode>::decode(&mut encoded.as_slice()).ok()
+}
+
+/// Read the raw value at a key.
+pub async fn get_storage(key: StorageKey, client: &Client, at: Hash) -> Option<StorageData> {
 	let serialized_key = to_json_value(key).expect("StorageKey serialization infallible");
 	let at = to_json_value(at).expect("Block hash serialization infallible");
-	let raw: Option<StorageData> = client
+	client
 		.request("state_getStorage", Params::Array(vec![serialized_key, at]))
 		.await
-		.expect("Storage request failed");
-	let encoded = raw.map(|d| d.0)?;
-	<T as Decode>::decode(&mut encoded.as_slice()).ok()
+		.expect("Storage request failed")
+}
+
+/// Get at most `count` keys under `prefix` that come strictly after `start_key`.
+///
+/// Unlike [`get_pairs`], this is a safe RPC call, so all keys under a prefix can be enumerated
+/// from any node, one page at a time.
+pub async fn get_keys_paged(
+	prefix: StorageKey,
+	count: u32,
+	start_key: Option<StorageKey>,
+	client: &Client,
+	at: Hash,
+) -> Vec<StorageKey> {
+	let serialized_prefix = to_json_value(prefix).expect("StorageKey serialization infallible");
+	let count = to_json_value(count).expect("u32 serialization infallible");
+	let start_key = to_json_value(start_key).expect("StorageKey serialization infallible");
+	let at = to_json_value(at).expect("Block hash serialization infallible");
+	client
+		.request("state_getKeysPaged", Params::Array(vec![serialized_prefix, count, start_key, at]))
+		.await
+		.expect("Storage state_getKeysPaged failed")
 }
 
 /// Get all storage pairs located under a certain prefix.
@@ -119,12 +147,18 @@ pub async fn get_pairs(
 	client: &Client,
 	at: Hash,
 ) -> Vec<(StorageKey, StorageData)> {
+	try_get_pairs(prefix, client, at).await.expect("Storage state_getPairs failed")
+}
+
+/// Like [`get_pairs`], but returns the error of the node, e.g. if it denies unsafe RPC calls.
+pub async fn try_get_pairs(
+	prefix: StorageKey,
+	client: &Client,
+	at: Hash,
+) -> Result<Vec<(StorageKey, StorageData)>, RpcError> {
 	let serialized_prefix = to_json_value(prefix).expect("StorageKey serialization infallible");
 	let at = to_json_value(at).expect("Block hash serialization infallible");
-	client
-		.request("state_getPairs", Params::Array(vec![serialized_prefix, at]))
-		.await
-		.expect("Storage state_getPairs failed")
+	client.request("state_getPairs", Params::Array(vec![serialized_prefix, at])).await
 }
 
 pub async fn get_pairs_http(
@@ -247,6 +281,15 @@ pub async fn get_header<H: serde::de::DeserializeOwned>(client: &Client, at: Has
 		.expect("get chain header request failed")
 }
 
+/// Get the hash of the block with the given number in the canonical chain, if it exists.
+pub async fn get_block_hash(client: &Client, number: u32) -> Option<Hash> {
+	let number = to_json_value(number).expect("Block number serialization infallible");
+	client
+		.request("chain_getBlockHash", Params::Array(vec![number]))
+		.await
+		.expect("get chain block hash request failed")
+}
+
 /// Get the block at the the given hash.
 pub async fn get_block<B: serde::de::DeserializeOwned>(client: &Client, at: Hash) -> Option<B> {
 	let at = to_json_value(at).expect("Block hash serialization infallible");
@@ -280,11 +323,71 @@ pub async fn get_runtime_version(client: &Client, at: Hash) -> sp_version::Runti
 	data.expect("Version must exist")
 }
 
+/// Get the properties of the chain, such as its token symbol and decimals.
+///
+/// The properties are an arbitrary json object, so the type to decode them into is generic.
+pub async fn get_system_properties<P: serde::de::DeserializeOwned>(client: &Client) -> P {
+	client.request("system_properties", Params::None).await.expect("Failed to fetch properties")
+}
+
 /// Get the size of a storage map.
 pub async fn get_storage_size(key: StorageKey, client: &Client, at: Hash) -> Option<u64> {
+	try_get_storage_size(key, client, at).await.unwrap()
+}
+
+/// Like [`get_storage_size`], but returns the error of the node.
+///
+/// The size of a single value can always be queried, but summing the size of all values under a
+/// prefix is an unsafe RPC call.
+pub async fn try_get_storage_size(
+	key: StorageKey,
+	client: &Client,
+	at: Hash,
+) -> Result<Option<u64>, RpcError> {
 	let at = to_json_value(at).expect("Block hash serialization infallible");
 	let key = to_json_value(key).expect("extrinsic serialization infallible");
-	client.request("state_getStorageSize", Params::Array(vec![key, at])).await.unwrap()
+	client.request("state_getStorageSize", Params::Array(vec![key, at])).await
+}
+
+/// Get at most `count` keys under `prefix` in the child trie `child_key` that come strictly after
+/// `start_key`. `child_key` is the full key of the child trie in the main trie, e.g. starting with
+/// `:child_storage:default:`.
+pub async fn get_child_keys_paged(
+	child_key: StorageKey,
+	prefix: StorageKey,
+	count: u32,
+	start_key: Option<StorageKey>,
+	client: &Client,
+	at: Hash,
+) -> Vec<StorageKey> {
+	let child_key = to_json_value(child_key).expect("StorageKey serialization infallible");
+	let prefix = to_json_value(prefix).expect("StorageKey serialization infallible");
+	let count = to_json_value(count).expect("u32 serialization infallible");
+	let start_key = to_json_value(start_key).expect("StorageKey serialization infallible");
+	let at = to_json_value(at).expect("Block hash serialization infallible");
+	client
+		.request(
+			"childstate_getKeysPaged",
+			Params::Array(vec![child_key, prefix, count, start_key, at]),
+		)
+		.await
+		.expect("Storage childstate_getKeysPaged failed")
+}
+
+/// Get the size of the value at `key` in the child trie `child_key`.
+pub async fn get_child_storage_size(
+	child_key: StorageKey,
+	key: StorageKey,
+	client: &Client,
+	at: Hash,
+) -> Option<u64> {
+	let child_key = to_json_value(child_key).expect("StorageKey serialization infallible");
+	let key = to_json_value(key).expect("StorageKey serialization infallible");
+	let at = to_json_value(at).expect("Block hash serialization infallible");
+	client
+		.request("childstate_getStorageSize", Params::Array(vec![child_key, key, at]))
+		.await
+		.expect("Storage childstate_getStorageSize failed")
 }
 
 #[cfg(test)]
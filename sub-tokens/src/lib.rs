@@ -14,8 +14,8 @@
 //! let dots = DOT::from(1_000_000_000_000u128);
 //!
 //! // provides display and format implementations.
-//! assert_eq!(format!("{}", dots), "100,000 DOT");
-//! assert_eq!(format!("{:?}", dots), "100,000 DOT (1,000,000,000,000)");
+//! assert_eq!(format!("{}", dots), "100.000 DOT");
+//! assert_eq!(format!("{:?}", dots), "100.000 DOT (1,000,000,000,000)");
 //! ```
 //!
 //! ## Custom tokens
@@ -29,14 +29,15 @@
 //! impl_token!(KIZ, 1000u32, u32);
 //!
 //! let kiz = KIZ::from(100);
-//! assert_eq!(format!("{}", kiz), "0,100 KIZ");
-//! assert_eq!(format!("{:?}", kiz), "0,100 KIZ (100)");
+//! assert_eq!(format!("{}", kiz), "0.100 KIZ");
+//! assert_eq!(format!("{:?}", kiz), "0.100 KIZ (100)");
 //! ```
 //!
 //! ## Dynamic Tokens
 //!
 //! A dynamic token is also provided that can be used in applications that need to dynamically
-//! decide to which chain to connect. This token type works only with u128.
+//! decide to which chain to connect. This token type works only with u128. Its name and decimal
+//! points are global to the process.
 //!
 //! ```
 //! // the alias that you will use in your crate.
@@ -44,20 +45,486 @@
 //!
 //! // set the name
 //! sub_tokens::dynamic::set_name("CST");
-//! sub_tokens::dynamic::set_decimal_points(1000);
+//! sub_tokens::dynamic::set_decimal_points(1000).unwrap();
 //!
-//! assert_eq!(format!("{}", MyToken::from(100)), "0,100 CST");
-//! assert_eq!(format!("{:?}", MyToken::from(100)), "0,100 CST (100)");
+//! assert_eq!(format!("{}", MyToken::from(100)), "0.100 CST");
+//! assert_eq!(format!("{:?}", MyToken::from(100)), "0.100 CST (100)");
 //! ```
+//!
+//! ## Formatting
+//!
+//! By default, three fractional digits are displayed. The precision of the formatter is used if
+//! given, and the alternate mode gives a compact rendering. Any other rendering can be built with
+//! a [`Format`].
+//!
+//! ```
+//! use sub_tokens::{Format, KSM};
+//!
+//! let ksm = KSM::from(1_500_250_000_000_000);
+//! assert_eq!(format!("{}", ksm), "1,500.250 KSM");
+//! assert_eq!(format!("{:.1}", ksm), "1,500.2 KSM");
+//! assert_eq!(format!("{:#}", ksm), "1.5k KSM");
+//! assert_eq!(ksm.format(&Format::default().separators(Some(' '), ',')), "1 500,250 KSM");
+//! ```
+//!
+//! ## Parsing
+//!
+//! All tokens implement [`std::str::FromStr`], and parse human-readable amounts into base units.
+//! The amount may have `,` thousands separators, a `.` decimal point, a `k`, `m` or `b` suffix
+//! (thousand, million and billion) and the name of the token. Amounts with more decimals than the
+//! token supports, or with the name of another token, are rejected.
+//!
+//! ```
+//! use sub_tokens::{DOT, KSM, ParseError};
+//!
+//! assert_eq!("1,234.5 DOT".parse::<DOT>().unwrap().value(), 12_345_000_000_000);
+//! assert_eq!("0.001 KSM".parse::<KSM>().unwrap().value(), 1_000_000_000);
+//! assert_eq!("1.2k".parse::<DOT>().unwrap().value(), 12_000_000_000_000);
+//! assert_eq!("1 KSM".parse::<DOT>().unwrap_err(), ParseError::WrongToken("KSM".into()));
+//! ```
+//!
+//! ## Arithmetic and serde
+//!
+//! All tokens are `Copy`, ordered, and support `+`, `-`, `Sum`, multiplication by a `Perbill`, and
+//! checked and saturating addition and subtraction. They are serialized as a string of their base
+//! units, which is lossless.
+//!
+//! ```
+//! use sub_tokens::DOT;
+//! use sp_arithmetic::Perbill;
+//!
+//! let total: DOT = vec![DOT::from(10), DOT::from(20)].into_iter().sum();
+//! assert_eq!(total.value(), 30);
+//! assert_eq!((total * Perbill::from_percent(50)).value(), 15);
+//! assert!(DOT::from(1) < total);
+//! assert_eq!(DOT::from(1).checked_sub(total), None);
+//! assert_eq!(serde_json::to_string(&total).unwrap(), "\"30\"");
+//! ```
+//!
+//! If the token is not known at the time of parsing, e.g. in command line arguments of a tool
+//! that detects the chain later, an [`Amount`] can be parsed first and converted later.
+//!
+//! ## Registry
+//!
+//! The [`registry`] knows the tokens of the main chains, and detects the token of any other chain
+//! from the `system_properties` RPC of its node. The detected token can then be used by the
+//! dynamic token.
 
 #[doc(hidden)]
 pub use separator::Separatable;
 #[doc(hidden)]
-pub use std::{cell::RefCell, convert::TryInto, fmt};
+pub use serde;
+#[doc(hidden)]
+pub use sp_arithmetic::Perbill;
+#[doc(hidden)]
+pub use std::{convert::TryInto, fmt, str::FromStr};
+
+/// Error of parsing a token amount.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParseError {
+	/// The amount is empty.
+	Empty,
+	/// The number of the amount is malformed.
+	InvalidNumber(String),
+	/// The amount has more decimals than the token supports.
+	TooManyDecimals(usize),
+	/// The amount names another token.
+	WrongToken(String),
+	/// The decimal points of the token are not a power of ten.
+	UnsupportedDecimals(u128),
+	/// The amount does not fit in the token.
+	Overflow,
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Empty => write!(f, "empty amount"),
+			Self::InvalidNumber(n) => write!(f, "invalid number `{}`", n),
+			Self::TooManyDecimals(max) => write!(f, "too many decimals, at most {} allowed", max),
+			Self::WrongToken(t) => write!(f, "unexpected token `{}`", t),
+			Self::UnsupportedDecimals(d) => write!(f, "decimal points {} not a power of ten", d),
+			Self::Overflow => write!(f, "amount too large"),
+		}
+	}
+}
+
+impl std::error::Error for ParseError {}
+
+/// A parsed, human-readable amount of some token, not yet converted into base units.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Amount {
+	/// The digits before the decimal point.
+	integer: String,
+	/// The digits after the decimal point, without trailing zeros.
+	fraction: String,
+	/// The power of ten of the `k`, `m` or `b` suffix.
+	exponent: u32,
+	/// The name of the token, if given.
+	unit: Option<String>,
+}
+
+impl Amount {
+	/// Convert into the base units of a token with the given decimal points and name.
+	pub fn to_base_units(&self, decimals: u128, name: &str) -> Result<u128, ParseError> {
+		if let Some(unit) = &self.unit {
+			if !unit.eq_ignore_ascii_case(name) {
+				return Err(ParseError::WrongToken(unit.clone()))
+			}
+		}
+
+		let digits = decimal_digits(decimals).ok_or(ParseError::UnsupportedDecimals(decimals))?;
+		let scale = digits + self.exponent;
+		if self.fraction.len() > scale as usize {
+			return Err(ParseError::TooManyDecimals(scale as usize))
+		}
+		let parse = |digits: &str| -> Result<u128, ParseError> {
+			if digits.is_empty() {
+				Ok(0)
+			} else {
+				digits.parse::<u128>().map_err(|_| ParseError::Overflow)
+			}
+		};
+		let pow = |exp: u32| 10u128.checked_pow(exp).ok_or(ParseError::Overflow);
+
+		let integer = parse(&self.integer)?.checked_mul(pow(scale)?).ok_or(ParseError::Overflow)?;
+		let fraction = parse(&self.fraction)?
+			.checked_mul(pow(scale - self.fraction.len() as u32)?)
+			.ok_or(ParseError::Overflow)?;
+		integer.checked_add(fraction).ok_or(ParseError::Overflow)
+	}
+}
+
+impl FromStr for Amount {
+	type Err = ParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parts = s.split_whitespace();
+		let number = parts.next().ok_or(ParseError::Empty)?;
+		let unit = parts.next().map(|u| u.to_string());
+		if let Some(extra) = parts.next() {
+			return Err(ParseError::WrongToken(extra.to_string()))
+		}
+		let invalid = || ParseError::InvalidNumber(number.to_string());
+
+		let (number, exponent) = match number.chars().last().map(|c| c.to_ascii_lowercase()) {
+			Some('k') => (&number[..number.len() - 1], 3),
+			Some('m') => (&number[..number.len() - 1], 6),
+			Some('b') => (&number[..number.len() - 1], 9),
+			_ => (number, 0),
+		};
+
+		let (integer, fraction) = match number.find('.') {
+			Some(at) => (&number[..at], &number[at + 1..]),
+			None => (number, ""),
+		};
+		if integer.is_empty() && fraction.is_empty() {
+			return Err(invalid())
+		}
+		if !fraction.chars().all(|c| c.is_ascii_digit()) {
+			return Err(invalid())
+		}
+
+		// thousands separators must separate groups of exactly three digits.
+		let groups = integer.split(',').collect::<Vec<_>>();
+		let valid_group = |(i, g): (usize, &&str)| {
+			g.chars().all(|c| c.is_ascii_digit()) &&
+				if i == 0 { groups.len() == 1 || (1..=3).contains(&g.len()) } else { g.len() == 3 }
+		};
+		if !groups.iter().enumerate().all(valid_group) {
+			return Err(invalid())
+		}
+
+		Ok(Self {
+			integer: groups.concat(),
+			fraction: fraction.trim_end_matches('0').to_string(),
+			exponent,
+			unit,
+		})
+	}
+}
+
+/// Parse a human-readable amount into the base units of a token with the given decimal points and
+/// name.
+pub fn parse_units(s: &str, decimals: u128, name: &str) -> Result<u128, ParseError> {
+	s.parse::<Amount>()?.to_base_units(decimals, name)
+}
+
+/// The number of decimal digits of a token with the given decimal points, e.g. 3 for 1000.
+///
+/// Returns `None` if the decimal points are not a power of ten.
+pub fn decimal_digits(decimals: u128) -> Option<u32> {
+	let mut digits = 0u32;
+	let mut rest = decimals;
+	while rest > 1 && rest % 10 == 0 {
+		rest /= 10;
+		digits += 1;
+	}
+	if rest == 1 {
+		Some(digits)
+	} else {
+		None
+	}
+}
+
+/// The suffixes of [`Format::si`], each a thousand times the previous one.
+const SI_SUFFIXES: [&str; 4] = ["", "k", "M", "B"];
+
+/// How to render an amount of a token.
+///
+/// The fraction is truncated, never rounded, so that an amount is never displayed larger than it
+/// is.
+///
+/// ```
+/// use sub_tokens::{Format, DOT};
+///
+/// let dots = DOT::from(12_345_678_900_000);
+/// assert_eq!(dots.format(&Format::default()), "1,234.567 DOT");
+/// let full = Format::default().full_precision().trim_zeros(true);
+/// assert_eq!(dots.format(&full), "1,234.56789 DOT");
+/// assert_eq!(dots.format(&Format::default().separators(Some('.'), ',')), "1.234,567 DOT");
+/// assert_eq!(dots.format(&Format::compact()), "1.23k DOT");
+/// ```
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Format {
+	precision: Option<usize>,
+	trim_zeros: bool,
+	thousands_separator: Option<char>,
+	decimal_separator: char,
+	si: bool,
+}
+
+impl Default for Format {
+	/// Three fractional digits, `,` thousands separators and a `.` decimal separator.
+	fn default() -> Self {
+		Self {
+			precision: Some(3),
+			trim_zeros: false,
+			thousands_separator: Some(','),
+			decimal_separator: '.',
+			si: false,
+		}
+	}
+}
+
+impl Format {
+	/// A short rendering: `k`, `M` or `B` suffixes, at most two fractional digits, no trailing
+	/// zeros and no thousands separators, e.g. `1.23k DOT`.
+	pub fn compact() -> Self {
+		Self::default().si(true).precision(2).trim_zeros(true).separators(None, '.')
+	}
+
+	/// Render this many fractional digits.
+	pub fn precision(mut self, precision: usize) -> Self {
+		self.precision = Some(precision);
+		self
+	}
+
+	/// Render all of the fractional digits of the token.
+	pub fn full_precision(mut self) -> Self {
+		self.precision = None;
+		self
+	}
+
+	/// Remove the trailing zeros of the fraction, and the decimal separator if nothing remains.
+	pub fn trim_zeros(mut self, trim: bool) -> Self {
+		self.trim_zeros = trim;
+		self
+	}
+
+	/// Use the given thousands separator, if any, and decimal separator.
+	pub fn separators(mut self, thousands: Option<char>, decimal: char) -> Self {
+		self.thousands_separator = thousands;
+		self.decimal_separator = decimal;
+		self
+	}
+
+	/// Scale large amounts down with a `k`, `M` or `B` suffix.
+	pub fn si(mut self, si: bool) -> Self {
+		self.si = si;
+		self
+	}
+
+	/// The format of a [`fmt::Formatter`]: the default format, with its precision if given, or
+	/// the compact format in the alternate mode (`{:#}`).
+	pub fn from_formatter(f: &fmt::Formatter) -> Self {
+		let format = if f.alternate() { Self::compact() } else { Self::default() };
+		match f.precision() {
+			Some(precision) => format.precision(precision),
+			None => format,
+		}
+	}
+
+	/// Render `value` base units of the token with the given decimal points and name.
+	///
+	/// Panics if `decimals` is not a power of ten.
+	pub fn render(&self, value: u128, decimals: u128, name: &str) -> String {
+		let digits = decimal_digits(decimals).expect("decimal points must be a power of ten");
+		let raw = format!("{:0>width$}", value, width = digits as usize + 1);
+		let (mut integer, mut fraction) = raw.split_at(raw.len() - digits as usize);
+		let shifted;
+
+		let mut suffix = SI_SUFFIXES[0];
+		if self.si {
+			let scale = ((integer.len() - 1) / 3).min(SI_SUFFIXES.len() - 1);
+			if scale > 0 {
+				suffix = SI_SUFFIXES[scale];
+				shifted = format!("{}{}", &integer[integer.len() - 3 * scale..], fraction);
+				fraction = &shifted;
+				integer = &integer[..integer.len() - 3 * scale];
+			}
+		}
+
+		let mut fraction = match self.precision {
+			Some(precision) if precision < fraction.len() => fraction[..precision].to_string(),
+			Some(precision) => format!("{:0<width$}", fraction, width = precision),
+			None => fraction.to_string(),
+		};
+		if self.trim_zeros {
+			fraction.truncate(fraction.trim_end_matches('0').len());
+		}
+
+		let mut rendered = String::new();
+		for (i, digit) in integer.chars().enumerate() {
+			if let Some(separator) = self.thousands_separator {
+				if i > 0 && (integer.len() - i) % 3 == 0 {
+					rendered.push(separator);
+				}
+			}
+			rendered.push(digit);
+		}
+		if !fraction.is_empty() {
+			rendered.push(self.decimal_separator);
+			rendered.push_str(&fraction);
+		}
+		format!("{}{} {}", rendered, suffix, name)
+	}
+}
+
+#[doc(hidden)]
+pub fn deserialize_base_units<'de, D: serde::Deserializer<'de>>(d: D) -> Result<u128, D::Error> {
+	struct Visitor;
+
+	impl<'de> serde::de::Visitor<'de> for Visitor {
+		type Value = u128;
+
+		fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+			write!(f, "an amount in base units, as a string")
+		}
+
+		fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<u128, E> {
+			v.parse().map_err(|_| E::invalid_value(serde::de::Unexpected::Str(v), &self))
+		}
+	}
+
+	d.deserialize_str(Visitor)
+}
+
+/// Implement the value semantics of a token type: accessors, comparison, arithmetic and serde.
+///
+/// Serde uses a string of the base units, since they often do not fit in the numbers of formats
+/// such as json.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! impl_token_ops {
+	($name:ident, $type:ty) => {
+		impl $name {
+			/// The amount in base units.
+			pub fn value(&self) -> $type {
+				self.0
+			}
+
+			/// Checked addition. Returns `None` on overflow.
+			pub fn checked_add(self, other: Self) -> Option<Self> {
+				self.0.checked_add(other.0).map(Self)
+			}
+
+			/// Checked subtraction. Returns `None` on underflow.
+			pub fn checked_sub(self, other: Self) -> Option<Self> {
+				self.0.checked_sub(other.0).map(Self)
+			}
+
+			/// Saturating addition.
+			pub fn saturating_add(self, other: Self) -> Self {
+				Self(self.0.saturating_add(other.0))
+			}
+
+			/// Saturating subtraction.
+			pub fn saturating_sub(self, other: Self) -> Self {
+				Self(self.0.saturating_sub(other.0))
+			}
+		}
+
+		impl std::ops::Add for $name {
+			type Output = Self;
+
+			fn add(self, other: Self) -> Self {
+				Self(self.0 + other.0)
+			}
+		}
+
+		impl std::ops::AddAssign for $name {
+			fn add_assign(&mut self, other: Self) {
+				self.0 += other.0
+			}
+		}
+
+		impl std::ops::Sub for $name {
+			type Output = Self;
+
+			fn sub(self, other: Self) -> Self {
+				Self(self.0 - other.0)
+			}
+		}
+
+		impl std::ops::SubAssign for $name {
+			fn sub_assign(&mut self, other: Self) {
+				self.0 -= other.0
+			}
+		}
+
+		impl std::ops::Mul<$crate::Perbill> for $name {
+			type Output = Self;
+
+			fn mul(self, ratio: $crate::Perbill) -> Self {
+				Self(ratio * self.0)
+			}
+		}
+
+		impl std::iter::Sum for $name {
+			fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+				iter.fold(Self(0), |acc, x| acc + x)
+			}
+		}
+
+		impl<'a> std::iter::Sum<&'a $name> for $name {
+			fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+				iter.copied().sum()
+			}
+		}
+
+		impl $crate::serde::Serialize for $name {
+			fn serialize<S: $crate::serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+				s.serialize_str(&self.0.to_string())
+			}
+		}
+
+		impl<'de> $crate::serde::Deserialize<'de> for $name {
+			fn deserialize<D: $crate::serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+				use $crate::{serde::de::Error, TryInto};
+				$crate::deserialize_base_units(d)?
+					.try_into()
+					.map(Self)
+					.map_err(|_| D::Error::custom("amount too large"))
+			}
+		}
+	};
+}
 
 #[macro_export]
 macro_rules! impl_token {
 	($name:ident, $decimals:expr, $type:ty) => {
+		#[derive(Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 		pub struct $name($type);
 
 		impl $name {
@@ -66,16 +533,30 @@ macro_rules! impl_token {
 			}
 		}
 
+		$crate::impl_token_ops!($name, $type);
+
+		impl $crate::FromStr for $name {
+			type Err = $crate::ParseError;
+
+			fn from_str(s: &str) -> Result<Self, Self::Err> {
+				use $crate::TryInto;
+				$crate::parse_units(s, $decimals as u128, stringify!($name))?
+					.try_into()
+					.map(Self)
+					.map_err(|_| $crate::ParseError::Overflow)
+			}
+		}
+
+		impl $name {
+			/// Render this amount with the given format.
+			pub fn format(&self, format: &$crate::Format) -> String {
+				format.render(self.0 as u128, $decimals as u128, stringify!($name))
+			}
+		}
+
 		impl $crate::fmt::Display for $name {
 			fn fmt(&self, f: &mut $crate::fmt::Formatter) -> $crate::fmt::Result {
-				use $crate::Separatable;
-				write!(
-					f,
-					"{},{:0>3} {}",
-					(self.0 / $decimals).separated_string(),
-					self.0 % $decimals / ($decimals / 1000),
-					stringify!($name)
-				)
+				write!(f, "{}", self.format(&$crate::Format::from_formatter(f)))
 			}
 		}
 
@@ -84,10 +565,8 @@ macro_rules! impl_token {
 				use $crate::Separatable;
 				write!(
 					f,
-					"{},{:0>3} {} ({})",
-					self.0 / $decimals,
-					self.0 % $decimals / ($decimals / 1000),
-					stringify!($name),
+					"{} ({})",
+					self.format(&$crate::Format::from_formatter(f)),
 					self.0.separated_string(),
 				)
 			}
@@ -99,63 +578,264 @@ impl_token!(DOT, 1_0_000_000_000u128, u128);
 impl_token!(WND, 1_000_000_000_000u128, u128);
 impl_token!(KSM, 1_000_000_000_000u128, u128);
 
+pub mod registry;
+
 pub mod dynamic {
 	use super::*;
-	use std::{cell::RefCell, fmt};
+	use std::{borrow::Cow, fmt, sync::RwLock};
+
+	/// The name and decimal points of the dynamic token.
+	struct Config {
+		name: Cow<'static, str>,
+		decimal_points: u128,
+	}
+
+	lazy_static::lazy_static! {
+		/// The configuration of the dynamic token, shared by all threads of the process, so that
+		/// tasks of multi-threaded executors always see the same token.
+		static ref CONFIG: RwLock<Config> =
+			RwLock::new(Config { name: Cow::Borrowed("UNIT"), decimal_points: 1_000_000_000_000 });
+	}
 
-	thread_local! {
-		/// Decimal points of the currency based on the network.
-		static DECIMAL_POINTS: RefCell<u128> = RefCell::new(1_000_000_000_000_000_000u128);
+	fn with_config<R>(f: impl FnOnce(&Config) -> R) -> R {
+		f(&CONFIG.read().unwrap_or_else(|poisoned| poisoned.into_inner()))
+	}
+
+	fn with_config_mut(f: impl FnOnce(&mut Config)) {
+		f(&mut CONFIG.write().unwrap_or_else(|poisoned| poisoned.into_inner()))
+	}
 
-		/// Name of the currency token based on the network.
-		static TOKEN_NAME: RefCell<&'static str> = RefCell::new("AVAIL");
+	/// Set the name of the dynamic token, for all threads.
+	pub fn set_name(name: impl Into<Cow<'static, str>>) {
+		let name = name.into();
+		with_config_mut(|c| c.name = name);
 	}
 
-	pub fn set_name(name: &'static str) {
-		TOKEN_NAME.with(|v| *v.borrow_mut() = name);
+	/// Set the decimal points of the dynamic token, for all threads.
+	///
+	/// Fails, and keeps the current ones, if `decimal` is not a power of ten.
+	pub fn set_decimal_points(decimal: u128) -> Result<(), ParseError> {
+		decimal_digits(decimal).ok_or(ParseError::UnsupportedDecimals(decimal))?;
+		with_config_mut(|c| c.decimal_points = decimal);
+		Ok(())
 	}
 
-	pub fn set_decimal_points(decimal: u128) {
-		DECIMAL_POINTS.with(|v| *v.borrow_mut() = decimal);
+	/// The current name of the dynamic token.
+	pub fn name() -> String {
+		with_config(|c| c.name.to_string())
+	}
+
+	/// The current decimal points of the dynamic token.
+	pub fn decimal_points() -> u128 {
+		with_config(|c| c.decimal_points)
 	}
 
 	/// Wrapper to pretty-print currency token.
+	#[derive(Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 	pub struct DynamicToken(u128);
 
+	crate::impl_token_ops!(DynamicToken, u128);
+
 	impl DynamicToken {
 		pub fn from(x: u128) -> Self {
 			Self(x)
 		}
+
+		/// Convert an amount with the current decimal points and name.
+		pub fn from_amount(amount: &Amount) -> Result<Self, ParseError> {
+			with_config(|c| amount.to_base_units(c.decimal_points, &c.name)).map(Self)
+		}
+	}
+
+	impl FromStr for DynamicToken {
+		type Err = ParseError;
+
+		fn from_str(s: &str) -> Result<Self, Self::Err> {
+			Self::from_amount(&s.parse()?)
+		}
+	}
+
+	impl DynamicToken {
+		/// Render this amount with the given format, and the current decimal points and name.
+		pub fn format(&self, format: &Format) -> String {
+			with_config(|c| format.render(self.0, c.decimal_points, &c.name))
+		}
 	}
 
 	impl std::fmt::Debug for DynamicToken {
 		fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-			let num: u128 = self.0.try_into().unwrap();
-			let decimal = DECIMAL_POINTS.with(|v| *v.borrow());
-			let name = TOKEN_NAME.with(|v| *v.borrow());
-			write!(
-				f,
-				"{}.{:0>3} {} ({})",
-				self.0 / decimal,
-				self.0 % decimal / (decimal / 1000),
-				name,
-				num.separated_string()
-			)
+			write!(f, "{} ({})", self.format(&Format::from_formatter(f)), self.0.separated_string())
 		}
 	}
 
 	impl std::fmt::Display for DynamicToken {
 		fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-			let num: u128 = self.0.try_into().unwrap();
-			let decimal = DECIMAL_POINTS.with(|v| *v.borrow());
-			let name = TOKEN_NAME.with(|v| *v.borrow());
-			write!(
-				f,
-				"{},{:0>3} {}",
-				(num / decimal).separated_string(),
-				num % decimal / (decimal / 1000),
-				name
-			)
+			write!(f, "{}", self.format(&Format::from_formatter(f)))
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Mutex;
+
+	impl_token!(KIZ, 1000u32, u32);
+
+	lazy_static::lazy_static! {
+		/// Tests that change the dynamic token must not run concurrently.
+		static ref DYNAMIC: Mutex<()> = Mutex::new(());
+	}
+
+	#[test]
+	fn parse_works() {
+		assert_eq!("1".parse::<DOT>().unwrap().value(), 10_000_000_000);
+		assert_eq!("1,234.5 DOT".parse::<DOT>().unwrap().value(), 12_345_000_000_000);
+		assert_eq!("1234.5".parse::<DOT>().unwrap().value(), 12_345_000_000_000);
+		assert_eq!("0.001 KSM".parse::<KSM>().unwrap().value(), 1_000_000_000);
+		assert_eq!(".5 ksm".parse::<KSM>().unwrap().value(), 500_000_000_000);
+		assert_eq!("1.2k".parse::<DOT>().unwrap().value(), 12_000_000_000_000);
+		assert_eq!("2M WND".parse::<WND>().unwrap().value(), 2_000_000_000_000_000_000);
+		assert_eq!("0.0000000001 DOT".parse::<DOT>().unwrap().value(), 1);
+		assert_eq!("1.5000000000000".parse::<DOT>().unwrap().value(), 15_000_000_000);
+		assert_eq!("4.294 KIZ".parse::<KIZ>().unwrap().value(), 4294);
+	}
+
+	#[test]
+	fn parse_is_strict() {
+		let invalid = |s: &str| Err(ParseError::InvalidNumber(s.to_string()));
+		assert_eq!("".parse::<DOT>().map(|d| d.value()), Err(ParseError::Empty));
+		assert_eq!("1,23".parse::<DOT>().map(|d| d.value()), invalid("1,23"));
+		assert_eq!("1234,567".parse::<DOT>().map(|d| d.value()), invalid("1234,567"));
+		assert_eq!("1.2.3".parse::<DOT>().map(|d| d.value()), invalid("1.2.3"));
+		assert_eq!("1.5,0".parse::<DOT>().map(|d| d.value()), invalid("1.5,0"));
+		assert_eq!("-1".parse::<DOT>().map(|d| d.value()), invalid("-1"));
+		assert_eq!(".".parse::<DOT>().map(|d| d.value()), invalid("."));
+		assert_eq!("1x".parse::<DOT>().map(|d| d.value()), invalid("1x"));
+		assert_eq!(
+			"0.00000000001 DOT".parse::<DOT>().map(|d| d.value()),
+			Err(ParseError::TooManyDecimals(10))
+		);
+		assert_eq!(
+			"1 DOT KSM".parse::<DOT>().map(|d| d.value()),
+			Err(ParseError::WrongToken("KSM".to_string()))
+		);
+		assert_eq!("4.295 KIZ".parse::<KIZ>().map(|d| d.value()), Ok(4295));
+		assert_eq!("4,295k KIZ".parse::<KIZ>().map(|d| d.value()), Err(ParseError::Overflow));
+		assert_eq!(
+			"1b".parse::<Amount>().unwrap().to_base_units(30, "X"),
+			Err(ParseError::UnsupportedDecimals(30))
+		);
+	}
+
+	impl_token!(ONE, 10u16, u16);
+	impl_token!(NONE, 1u64, u64);
+	impl_token!(ETH, 1_000_000_000_000_000_000u128, u128);
+
+	#[test]
+	fn format_works() {
+		assert_eq!(format!("{}", DOT::from(0)), "0.000 DOT");
+		assert_eq!(format!("{}", DOT::from(1)), "0.000 DOT");
+		assert_eq!(format!("{:.10}", DOT::from(1)), "0.0000000001 DOT");
+		assert_eq!(format!("{}", DOT::from(123_456_789_012_345_678)), "12,345,678.901 DOT");
+		assert_eq!(format!("{:?}", KSM::from(1_000_000_000_000)), "1.000 KSM (1,000,000,000,000)");
+
+		// fewer than three decimals.
+		assert_eq!(format!("{}", ONE::from(255)), "25.500 ONE");
+		assert_eq!(format!("{:.0}", ONE::from(255)), "25 ONE");
+		assert_eq!(format!("{}", NONE::from(1234)), "1,234.000 NONE");
+		assert_eq!(NONE::from(1234).format(&Format::default().trim_zeros(true)), "1,234 NONE");
+
+		// many decimals.
+		let eth = ETH::from(1_234_567_890_123_456_789);
+		assert_eq!(format!("{}", eth), "1.234 ETH");
+		assert_eq!(eth.format(&Format::default().full_precision()), "1.234567890123456789 ETH");
+		assert_eq!(
+			format!("{}", ETH::from(u128::max_value())),
+			"340,282,366,920,938,463,463.374 ETH"
+		);
+
+		// suffixes and compact mode.
+		assert_eq!(format!("{:#}", DOT::from(12_345_678_900_000_000)), "1.23M DOT");
+		assert_eq!(format!("{:#}", DOT::from(10_000_000_000)), "1 DOT");
+		assert_eq!(format!("{:#}", DOT::from(5_000_000)), "0 DOT");
+		assert_eq!(format!("{:#.4}", DOT::from(5_000_000)), "0.0005 DOT");
+		assert_eq!(format!("{:#}", ETH::from(u128::max_value())), "340282366920.93B ETH");
+		assert_eq!(DOT::from(12_345_000_000_000).format(&Format::default().si(true)), "1.234k DOT");
+
+		// separators.
+		let format = Format::default().separators(Some('.'), ',');
+		assert_eq!(DOT::from(12_345_678_900_000_000).format(&format), "1.234.567,890 DOT");
+		let format = Format::default().separators(None, '.').precision(1);
+		assert_eq!(DOT::from(12_345_678_900_000_000).format(&format), "1234567.8 DOT");
+
+		let _guard = DYNAMIC.lock().unwrap();
+		dynamic::set_name("CST");
+		dynamic::set_decimal_points(1).unwrap();
+		assert_eq!(format!("{:.0}", dynamic::DynamicToken::from(1000)), "1,000 CST");
+	}
+
+	#[test]
+	fn arithmetic_works() {
+		let (a, b) = (KIZ::from(3), KIZ::from(u32::max_value() - 1));
+		assert_eq!((a + KIZ::from(4)).value(), 7);
+		assert_eq!((a - KIZ::from(1)).value(), 2);
+		assert_eq!(a.checked_add(b), None);
+		assert_eq!(a.saturating_add(b).value(), u32::max_value());
+		assert_eq!(a.checked_sub(b), None);
+		assert_eq!(a.saturating_sub(b).value(), 0);
+		assert_eq!((KIZ::from(1000) * Perbill::from_percent(25)).value(), 250);
+		assert_eq!([a, a, a].iter().sum::<KIZ>().value(), 9);
+		assert_eq!(vec![b, a].into_iter().max(), Some(b));
+
+		let mut c = a;
+		c += a;
+		c -= KIZ::from(1);
+		assert_eq!(c.value(), 5);
+	}
+
+	#[test]
+	fn serde_is_lossless() {
+		let dot = DOT::from(u128::max_value());
+		let json = serde_json::to_string(&dot).unwrap();
+		assert_eq!(json, format!("\"{}\"", u128::max_value()));
+		assert_eq!(serde_json::from_str::<DOT>(&json).unwrap(), dot);
+		assert!(serde_json::from_str::<DOT>("42").is_err());
+		assert!(serde_json::from_str::<KIZ>("\"4294967296\"").is_err());
+		assert!(serde_json::from_str::<DOT>("\"1 DOT\"").is_err());
+
+		let dynamic = dynamic::DynamicToken::from(7);
+		let json = serde_json::to_string(&dynamic).unwrap();
+		assert_eq!(serde_json::from_str::<dynamic::DynamicToken>(&json).unwrap(), dynamic);
+	}
+
+	#[test]
+	fn dynamic_parse_works() {
+		let _guard = DYNAMIC.lock().unwrap();
+		dynamic::set_name("CST");
+		dynamic::set_decimal_points(1000).unwrap();
+		assert_eq!(dynamic::set_decimal_points(1500), Err(ParseError::UnsupportedDecimals(1500)));
+		assert_eq!(dynamic::decimal_points(), 1000);
+		assert_eq!("1.5 CST".parse::<dynamic::DynamicToken>().unwrap().value(), 1500);
+		assert_eq!(
+			"1.5 DOT".parse::<dynamic::DynamicToken>().map(|d| d.value()),
+			Err(ParseError::WrongToken("DOT".to_string()))
+		);
+	}
+
+	#[test]
+	fn dynamic_is_shared_between_threads() {
+		let _guard = DYNAMIC.lock().unwrap();
+		dynamic::set_name(String::from("THR"));
+		dynamic::set_decimal_points(10).unwrap();
+
+		let rendered = std::thread::spawn(|| format!("{}", dynamic::DynamicToken::from(15)))
+			.join()
+			.unwrap();
+		assert_eq!(rendered, "1.500 THR");
+
+		std::thread::spawn(|| dynamic::set_name("OTHER")).join().unwrap();
+		assert_eq!(dynamic::name(), "OTHER");
+		assert_eq!(dynamic::decimal_points(), 10);
+	}
+}
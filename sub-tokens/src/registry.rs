@@ -0,0 +1,195 @@
+//! A registry of the tokens of known chains, and detection of the token of any chain from the
+//! `system_properties` RPC of its node.
+//!
+//! ```
+//! use sub_tokens::registry::{Registry, SystemProperties, TokenInfo};
+//!
+//! let registry = Registry::default();
+//! assert_eq!(registry.get("Kusama").unwrap().symbol, "KSM");
+//!
+//! // properties, as returned by `system_properties`, take precedence over the known chains.
+//! let properties: SystemProperties =
+//! 	serde_json::from_str(r#"{ "tokenSymbol": "ACA", "tokenDecimals": 12, "ss58Format": 10 }"#)
+//! 		.unwrap();
+//! let token = registry.detect("acala", &properties);
+//! assert_eq!(token, TokenInfo::new("ACA", 12, Some(10)));
+//! ```
+
+use crate::{dynamic, ParseError};
+use std::collections::BTreeMap;
+
+/// The token of a chain.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TokenInfo {
+	/// The symbol of the token, e.g. `DOT`.
+	pub symbol: String,
+	/// The number of decimal digits of the token, e.g. 10 for `DOT`.
+	pub decimals: u32,
+	/// The ss58 address format of the chain, if known.
+	pub ss58_format: Option<u16>,
+}
+
+impl TokenInfo {
+	/// Create a new token info.
+	pub fn new(symbol: &str, decimals: u32, ss58_format: Option<u16>) -> Self {
+		Self { symbol: symbol.to_string(), decimals, ss58_format }
+	}
+
+	/// The decimal points of the token, i.e. the number of base units in one token.
+	pub fn decimal_points(&self) -> u128 {
+		10u128.pow(self.decimals)
+	}
+
+	/// Use this token for [`dynamic::DynamicToken`].
+	///
+	/// Fails, and keeps the current dynamic token, if the decimals do not fit in a `u128`.
+	pub fn set_dynamic(&self) -> Result<(), ParseError> {
+		let decimal_points = 10u128.checked_pow(self.decimals).ok_or(ParseError::Overflow)?;
+		dynamic::set_decimal_points(decimal_points)?;
+		dynamic::set_name(self.symbol.clone());
+		Ok(())
+	}
+}
+
+impl Default for TokenInfo {
+	/// The token of a substrate development chain.
+	fn default() -> Self {
+		Self::new("UNIT", 12, Some(42))
+	}
+}
+
+/// A value of the properties that is either a single value, or one per token of the chain.
+#[derive(Debug, Clone, Eq, PartialEq, serde::Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+	/// A single value.
+	One(T),
+	/// Multiple values, of which the first is the native token.
+	Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+	/// The value of the native token.
+	pub fn first(self) -> Option<T> {
+		match self {
+			Self::One(t) => Some(t),
+			Self::Many(many) => many.into_iter().next(),
+		}
+	}
+}
+
+/// The response of the `system_properties` RPC. All of its fields are optional.
+#[derive(Debug, Clone, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemProperties {
+	/// The symbol of the token(s).
+	pub token_symbol: Option<OneOrMany<String>>,
+	/// The decimals of the token(s).
+	pub token_decimals: Option<OneOrMany<u32>>,
+	/// The ss58 address format.
+	pub ss58_format: Option<u16>,
+}
+
+impl SystemProperties {
+	/// The token info of these properties, if they contain both a symbol and decimals.
+	pub fn token(&self) -> Option<TokenInfo> {
+		let symbol = self.token_symbol.clone()?.first()?;
+		let decimals = self.token_decimals.clone()?.first()?;
+		Some(TokenInfo { symbol, decimals, ss58_format: self.ss58_format })
+	}
+}
+
+/// A registry of the tokens of chains, by name.
+#[derive(Debug, Clone)]
+pub struct Registry {
+	chains: BTreeMap<String, TokenInfo>,
+}
+
+impl Default for Registry {
+	/// A registry of the known chains.
+	fn default() -> Self {
+		let mut registry = Self::empty();
+		registry
+			.register("polkadot", TokenInfo::new("DOT", 10, Some(0)))
+			.register("kusama", TokenInfo::new("KSM", 12, Some(2)))
+			.register("westend", TokenInfo::new("WND", 12, Some(42)))
+			.register("rococo", TokenInfo::new("ROC", 12, Some(42)))
+			.register("substrate", TokenInfo::default())
+			.register("node", TokenInfo::default());
+		registry
+	}
+}
+
+impl Registry {
+	/// A registry with no chains.
+	pub fn empty() -> Self {
+		Self { chains: Default::default() }
+	}
+
+	/// Register the token of `chain`, replacing any previous one.
+	pub fn register(&mut self, chain: &str, token: TokenInfo) -> &mut Self {
+		self.chains.insert(chain.to_lowercase(), token);
+		self
+	}
+
+	/// The token of `chain`, which is matched case-insensitively against the spec or chain name.
+	pub fn get(&self, chain: &str) -> Option<&TokenInfo> {
+		self.chains.get(&chain.to_lowercase())
+	}
+
+	/// Detect the token of `chain`.
+	///
+	/// The `properties` of the node are used if they describe a token. Otherwise the known chain
+	/// is used, and finally the default token. A missing ss58 format in the properties is taken
+	/// from the known chain.
+	pub fn detect(&self, chain: &str, properties: &SystemProperties) -> TokenInfo {
+		let known = self.get(chain);
+		match properties.token() {
+			Some(mut token) => {
+				token.ss58_format = token.ss58_format.or(known.and_then(|k| k.ss58_format));
+				token
+			}
+			None => known.cloned().unwrap_or_default(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn properties(json: &str) -> SystemProperties {
+		serde_json::from_str(json).unwrap()
+	}
+
+	#[test]
+	fn properties_decode() {
+		let p = properties(r#"{"ss58Format":0,"tokenDecimals":10,"tokenSymbol":"DOT"}"#);
+		assert_eq!(p.token(), Some(TokenInfo::new("DOT", 10, Some(0))));
+
+		let p = properties(r#"{"tokenDecimals":[12,12],"tokenSymbol":["KAR","KUSD"]}"#);
+		assert_eq!(p.token(), Some(TokenInfo::new("KAR", 12, None)));
+
+		assert_eq!(properties("{}").token(), None);
+		assert_eq!(properties(r#"{"tokenSymbol":"X"}"#).token(), None);
+	}
+
+	#[test]
+	fn detect_works() {
+		let registry = Registry::default();
+		let empty = SystemProperties::default();
+
+		assert_eq!(registry.detect("Polkadot", &empty), TokenInfo::new("DOT", 10, Some(0)));
+		assert_eq!(registry.detect("unknown", &empty), TokenInfo::default());
+
+		// properties win, and the known ss58 format fills the gap.
+		let p = properties(r#"{"tokenDecimals":12,"tokenSymbol":"KSM"}"#);
+		assert_eq!(registry.detect("kusama", &p), TokenInfo::new("KSM", 12, Some(2)));
+
+		let mut registry = Registry::empty();
+		registry.register("Custom", TokenInfo::new("CST", 3, None));
+		assert_eq!(registry.detect("custom", &empty).decimal_points(), 1000);
+		// too many decimals are rejected, without touching the dynamic token.
+		assert_eq!(TokenInfo::new("BIG", 39, None).set_dynamic(), Err(ParseError::Overflow));
+	}
+}